@@ -6,7 +6,7 @@
 // serde_json = "1.0"
 // base64 = "0.21"
 // regex = "1.10"
-// anyhow = "1.0"
+// thiserror = "1.0"
 // lazy_static = "1.4"
 // chrono = "0.4"
 // sha2 = "0.10"
@@ -15,19 +15,28 @@
 // hmac = "0.12"
 // hex = "0.4"
 // crc = "3.0"
+// aes-gcm = "0.10"
+// curve25519-dalek = "3"
+// aes = "0.8"
+// cbc = "0.1"
+// bitflags = "2"
 
-use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
 use regex::Regex;
 use reqwest::{Client, header};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use sha2::{Sha256, Sha512, Digest};
 use ed25519_dalek::{Keypair, Signer, SecretKey, PublicKey};
 use pbkdf2::pbkdf2_hmac;
-use hmac::Hmac;
+use hmac::{Hmac, Mac};
+use aes_gcm::{aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Nonce};
+use cbc::cipher::{block_padding::NoPadding, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use curve25519_dalek::{edwards::CompressedEdwardsY, scalar::Scalar};
 
 // КОНФИГУРАЦИЯ
 const MNEMONIC: [&str; 24] = [
@@ -47,21 +56,88 @@ lazy_static::lazy_static! {
     };
 }
 
+// Файл с зашифрованной сессией Fragment и секрет для его ключа. С секретом из примера сессия
+// хранится только в памяти процесса и на диск не сохраняется
+const SESSION_PATH: &str = "fragment_session.bin";
+const SESSION_SECRET: &str = "ваш_секрет";
+// Журнал оплаченных покупок, по одной JSON-записи на строку
+const PURCHASES_PATH: &str = "fragment_purchases.jsonl";
+
+// Если оставить пустым или hash устареет, клиент найдет актуальный на страницах Fragment
 const FRAGMENT_HASH: &str = "ed3ec875a724358cea";
-const FRAGMENT_PUBLICKEY: &str = "91b296c356bb0894b40397b54565c11f4b29ea610b8e14d2ae1136a50c5d1d03";
-const FRAGMENT_WALLETS: &str = "te6cckECFgEAArEAAgE0AQsBFP8A9KQT9LzyyAsCAgEgAwYCAUgMBAIBIAgFABm+Xw9qJoQICg65D6AsAQLyBwEeINcLH4IQc2lnbrry4Ip/DQIBIAkTAgFuChIAGa3OdqJoQCDrkOuF/8AAUYAAAAA///+Il7w6CtQZIMze2+aVZS87QjJHoU5yqUljL1aSwzvDrCugAtzQINdJwSCRW49jINcLHyCCEGV4dG69IYIQc2ludL2wkl8D4IIQZXh0brqOtIAg1yEB0HTXIfpAMPpE+Cj6RDBYvZFb4O1E0IEBQdch9AWDB/QOb6ExkTDhgEDXIXB/2zzgMSDXSYECgLmRMOBw4g4NAeaO8O2i7fshgwjXIgKDCNcjIIAg1yHTH9Mf0x/tRNDSANMfINMf0//XCgAK+QFAzPkQmiiUXwrbMeHywIffArNQB7Dy0IRRJbry4IVQNrry4Ib4I7vy0IgikvgA3gGkf8jKAMsfAc8Wye1UIJL4D95w2zzYDgP27aLt+wL0BCFukmwhjkwCIdc5MHCUIccAs44tAdcoIHYeQ2wg10nACPLgkyDXSsAC8uCTINcdBscSwgBSMLDy0InXTNc5MAGk6GwShAe78uCT10rAAPLgk+1V4tIAAcAAkVvg69csCBQgkXCWAdcsCBwS4lIQseMPINdKERAPABCTW9sx4ddM0AByMNcsCCSOLSHy4JLSAO1E0NIAURO68tCPVFAwkTGcAYEBQNch1woA8uCO4sjKAFjPFsntVJPywI3iAJYB+kAB+kT4KPpEMFi68uCR7UTQgQFB1xj0BQSdf8jKAEAEgwf0U/Lgi44UA4MH9Fvy4Iwi1woAIW4Bs7Dy0JDiyFADzxYS9ADJ7VQAGa8d9qJoQBDrkOuFj8ACAUgVFAARsmL7UTQ1woAgABezJftRNBx1yHXCx+B27MAq";
-const FRAGMENT_ADDRESS: &str = "0:20c429e3bb195f46a582c10eb687c6ed182ec58237a55787f245ec992c337118";
-const TON_API_ENDPOINT: &str = "https://toncenter.com/api/v2/sendBoc";
+// Сеть для кошелька, API и TON Connect. Testnet — для проверки без реальных средств
+const NETWORK: Network = Network::Mainnet;
+const TON_API_URL: &str = "https://toncenter.com/api/v2";
+const TESTNET_API_URL: &str = "https://testnet.toncenter.com/api/v2";
+// Индексатор toncenter: поиск NFT по владельцу
+const TON_INDEX_URL: &str = "https://toncenter.com/api/v3";
+const TESTNET_INDEX_URL: &str = "https://testnet.toncenter.com/api/v3";
+// Сколько NFT индексатор отдает за один запрос; остальные загружаются по offset
+const NFT_INDEX_PAGE_SIZE: usize = 1000;
+// Срок действия подписанного сообщения и опрос seqno до подтверждения
+const MESSAGE_TTL: u32 = 60;
+const CONFIRMATION_POLL_SECS: u64 = 3;
+const CONFIRMATION_GRACE_SECS: u64 = 15;
+// Сколько транзакций кошелька загружается за запрос при поиске отправленного сообщения
+const TRANSACTIONS_PAGE_SIZE: usize = 20;
+// Ключ toncenter необязателен, но без него действует лимит 1 запрос в секунду
+const TONCENTER_API_KEY: &str = "";
+const FRAGMENT_DOMAIN: &str = "fragment.com";
+// Страницы, в конфиге которых Fragment отдает hash для API
+const FRAGMENT_HASH_PAGES: [&str; 2] = ["/", "/stars/buy"];
+const USER_AGENT: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 16_6 like Mac OS X) AppleWebKit/605.1.15";
+// V5R1 отправляет до 255 сообщений за одну подпись, V4R2 — до 4. У версий разные адреса
+const WALLET_VERSION: WalletVersion = WalletVersion::V4R2;
+const WALLET_V4R2_CODE: &str = "te6cckECFAEAAtQAART/APSkE/S88sgLAQIBIAIDAgFIBAUE+PKDCNcYINMf0x/THwL4I7vyZO1E0NMf0x/T//QE0VFDuvKhUVG68qIF+QFUEGT5EPKj+AAkpMjLH1JAyx9SMMv/UhD0AMntVPgPAdMHIcAAn2xRkyDXSpbTB9QC+wDoMOAhwAHjACHAAuMAAcADkTDjDQOkyMsfEssfy/8QERITAubQAdDTAyFxsJJfBOAi10nBIJJfBOAC0x8hghBwbHVnvSKCEGRzdHK9sJJfBeAD+kAwIPpEAcjKB8v/ydDtRNCBAUDXIfQEMFyBAQj0Cm+hMbOSXwfgBdM/yCWCEHBsdWe6kjgw4w0DghBkc3RyupJfBuMNBgcCASAICQB4AfoA9AQw+CdvIjBQCqEhvvLgUIIQcGx1Z4MesXCAGFAEywUmzxZY+gIZ9ADLaRfLH1Jgyz8gyYBA+wAGAIpQBIEBCPRZMO1E0IEBQNcgyAHPFvQAye1UAXKwjiOCEGRzdHKDHrFwgBhQBcsFUAPPFiP6AhPLassfyz/JgED7AJJfA+ICASAKCwBZvSQrb2omhAgKBrkPoCGEcNQICEekk30pkQzmkD6f+YN4EoAbeBAUiYcVnzGEAgFYDA0AEbjJftRNDXCx+AA9sp37UTQgQFA1yH0BDACyMoHy//J0AGBAQj0Cm+hMYAIBIA4PABmtznaiaEAga5Drhf/AABmvHfaiaEAQa5DrhY/AAG7SB/oA1NQi+QAFyMoHFcv/ydB3dIAYyMsFywIizxZQBfoCFMtrEszMyXP7AMhAFIEBCPRR8qcCAHCBAQjXGPoA0z/IVCBHgQEI9FHyp4IQbm90ZXB0gBjIywXLAlAGzxZQBPoCFMtqEssfyz/Jc/sAAgBsgQEI1xj6ANM/MFIkgQEI9Fnyp4IQZHN0cnB0gBjIywXLAlAFzxZQA/oCE8tqyx8Syz/Jc/sAAAr0AMntVGliJeU=";
+const WALLET_V5R1_CODE: &str = "te6ccgECFAEAAoEAART/APSkE/S88sgLAQIBIAIDAgFIBAUBAvIOAtzQINdJwSCRW49jINcLHyCCEGV4dG69IYIQc2ludL2wkl8D4IIQZXh0brqOtIAg1yEB0HTXIfpAMPpE+Cj6RDBYvZFb4O1E0IEBQdch9AWDB/QOb6ExkTDhgEDXIXB/2zzgMSDXSYECgLmRMOBw4hAPAgEgBgcCASAICQAZvl8PaiaECAoOuQ+gLAIBbgoLAgFIDA0AGa3OdqJoQCDrkOuF/8AAGa8d9qJoQBDrkOuFj8AAF7Ml+1E0HHXIdcLH4AARsmL7UTQ1woAgAR4g1wsfghBzaWduuvLgin8PAeaO8O2i7fshgwjXIgKDCNcjIIAg1yHTH9Mf0x/tRNDSANMfINMf0//XCgAK+QFAzPkQmiiUXwrbMeHywIffArNQB7Dy0IRRJbry4IVQNrry4Ib4I7vy0IgikvgA3gGkf8jKAMsfAc8Wye1UIJL4D95w2zzYEAP27aLt+wL0BCFukmwhjkwCIdc5MHCUIccAs44tAdcoIHYeQ2wg10nACPLgkyDXSsAC8uCTINcdBscSwgBSMLDy0InXTNc5MAGk6GwShAe78uCT10rAAPLgk+1V4tIAAcAAkVvg69csCBQgkXCWAdcsCBwS4lIQseMPINdKERITAJYB+kAB+kT4KPpEMFi68uCR7UTQgQFB1xj0BQSdf8jKAEAEgwf0U/Lgi44UA4MH9Fvy4Iwi1woAIW4Bs7Dy0JDiyFADzxYS9ADJ7VQAcjDXLAgkji0h8uCS0gDtRNDSAFETuvLQj1RQMJExnAGBAUDXIdcKAPLgjuLIygBYzxbJ7VST8sCN4gAQk1vbMeHXTNA=";
+// Код Highload Wallet v3 (BOC из github.com/ton-blockchain/highload-wallet-contract-v3)
+const HIGHLOAD_V3_CODE: &str = "te6cckECEAEAAigAART/APSkE/S88sgLAQIBIAINAgFIAwQAeNAg10vAAQHAYLCRW+EB0NMDAXGwkVvg+kAw+CjHBbORMODTHwGCEK5C5aS6nYBA1yHXTPgqAe1V+wTgMAIBIAUKAgJzBgcAEa3OdqJoa4X/wAIBIAgJABqrtu1E0IEBItch1ws/ABiqO+1E0IMH1yHXCx8CASALDAAbuabu1E0IEBYtch1wsVgA5bi/Ltou37IasJAoQJsO1E0IEBINch9AT0BNM/0xXRBY4b+CMloVIQuZ8ybfgjBaoAFaESuZIwbd6SMDPikjAz4lIwgA30D2+hntAh1yHXCgCVXwN/2zHgkTDiWYAN9A9voZzQAdch1woAk3/bMeCRW+JwgB9vLUgwjXGNEh+QDtRNDT/9Mf9AT0BNM/0xXR+CMhoVIguY4SM234IySqAKESuZJtMt5Y+CMB3lQWdfkQ8qEG0NMf1NMH0wzTCdM/0xXRUWi68qJRWrrypvgjKqFSULzyowT4I7vyo1MEgA30D2+hmdAk1yHXCgDyZJEw4g4B/lMJgA30D2+hjhPQUATXGNIAAfJkyFjPFs+DAc8WjhAwyCTPQM+DhAlQBaGlFM9A4vgAyUA5gA30FwTIy/8Tyx/0ABL0ABLLPxLLFcntVPgPIdDTAAHyZdMCAXGwkl8D4PpAAdcLAcAA8qX6QDH6ADH0AfoAMfoAMYBg1yHTAAEPACDyZdIAAZPUMdGRMOJysfsAtYW/Aw==";
+const HIGHLOAD_SUBWALLET_ID: u32 = 0x10ad;
+// Сколько секунд сообщение highload-кошелька остается действительным
+const HIGHLOAD_TIMEOUT: u32 = 600;
+// Выданные query_id, чтобы после перезапуска не отправить повтор
+const HIGHLOAD_QUERY_IDS_PATH: &str = "highload_query_ids.json";
+// Оплата покупок Fragment с highload-кошелька той же мнемоники (у него свой адрес)
+const USE_HIGHLOAD_WALLET: bool = false;
+const PREMIUM_MONTHS: [u8; 3] = [3, 6, 12];
+// NFT-коллекции Fragment
+const TELEGRAM_USERNAMES_COLLECTION: &str = "EQCA14o1-VWhS2efqoh_9M1b_A9DtKTuoqfmkn83AbJzwnPi";
+const ANONYMOUS_NUMBERS_COLLECTION: &str = "EQAOQdwdw8kGftJCSFgOErM1mBjYPe4DBPq8-AhF6vr9si5N";
+// TON на комиссию NFT-контракта при передаче; остаток возвращается на кошелек
+const NFT_TRANSFER_AMOUNT: u64 = 50_000_000;
+// TON на комиссию jetton-кошельков при переводе; остаток возвращается на кошелек
+const JETTON_TRANSFER_AMOUNT: u64 = 50_000_000;
+// USDT на TON (6 знаков после запятой)
+const USDT_MASTER: &str = "EQCxE6mUtQJKFnGfaROTKOt1lZbDiiX1kCixRv7Nw2Id_sDs";
+// 4 символа допустимы только у коллекционных username с Fragment
+const USERNAME_MIN_LEN: usize = 4;
+const USERNAME_MAX_LEN: usize = 32;
 
 // СТРУКТУРЫ ДАННЫХ
 #[derive(Debug, Deserialize)]
 struct RecipientResponse {
-    found: Option<FoundRecipient>,
+    found: Option<Recipient>,
 }
 
-#[derive(Debug, Deserialize)]
-struct FoundRecipient {
+// Результат поиска получателя: все поля, которые возвращает Fragment
+#[derive(Debug, Clone, Deserialize)]
+struct Recipient {
     recipient: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    photo: Option<String>,
+    #[serde(default)]
+    myself: bool,
+}
+
+impl Recipient {
+    // photo приходит HTML-тегом <img src="...">, достаем из него ссылку
+    fn photo_url(&self) -> Option<String> {
+        let re = Regex::new(r#"src="([^"]+)""#).unwrap();
+        re.captures(self.photo.as_deref()?)
+            .map(|c| c[1].to_string())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -80,18 +156,306 @@ struct Transaction {
     messages: Vec<Message>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct Message {
     address: String,
     amount: String,
     payload: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct PriceResponse {
+    ok: Option<bool>,
+    cur_price: Option<String>,
+}
+
+// Текущая цена товара на Fragment
+#[derive(Debug, Clone, Copy)]
+struct Quote {
+    ton: f64,
+    usd: f64,
+}
+
+// Параметры покупки, которые выбирает клиент
+#[derive(Debug, Clone, Default)]
+struct PurchaseOptions {
+    // Показать получателю, кто отправил Stars или Premium (по умолчанию подарок анонимный)
+    show_sender: bool,
+    // Не платить больше указанной суммы в TON
+    max_price_ton: Option<f64>,
+}
+
+// Запись об оплаченной покупке для журнала
+#[derive(Debug, Clone, Serialize)]
+struct PurchaseRecord {
+    product: String,
+    recipient: String,
+    req_id: String,
+    show_sender: bool,
+    // Адрес первого сообщения и общая сумма всех сообщений счета
+    address: String,
+    amount_nano: u64,
+    messages: usize,
+    comment: String,
+    tx_hash: String,
+    created_at: i64,
+}
+
 #[derive(Debug, Serialize)]
 struct SendBocRequest {
     boc: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct WalletInfoResponse {
+    ok: bool,
+    result: Option<WalletInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RunGetMethodResponse {
+    ok: bool,
+    result: Option<GetMethodResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetMethodResult {
+    stack: Vec<Value>,
+    exit_code: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct WalletInfo {
+    #[serde(deserialize_with = "deserialize_u64_string")]
+    balance: u64,
+    seqno: Option<u32>,
+    #[serde(default)]
+    account_state: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionsResponse {
+    ok: bool,
+    result: Option<Vec<RawTransaction>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTransaction {
+    utime: u64,
+    transaction_id: TransactionId,
+    in_msg: Option<RawMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct TransactionId {
+    lt: String,
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawMessage {
+    // Hash ячейки сообщения в base64
+    hash: String,
+}
+
+// ОШИБКИ
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("Криптография: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("Ячейка: {0}")]
+    Cell(#[from] CellError),
+    #[error("Адрес: {0}")]
+    Address(#[from] AddressError),
+    #[error("Блокчейн: {0}")]
+    Chain(#[from] ChainError),
+    #[error(transparent)]
+    Fragment(#[from] FragmentError),
+    #[error("Конфигурация: {0}")]
+    Config(#[from] ConfigError),
+    #[error("Проверка: {0}")]
+    Verification(#[from] VerificationError),
+    #[error(transparent)]
+    Username(#[from] UsernameError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // Временные сбои, после которых операцию можно повторить как есть
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Fragment(e) => e.is_retryable(),
+            Self::Chain(e) => e.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CryptoError {
+    #[error("Неверная мнемоника")]
+    InvalidMnemonic,
+    #[error("Неверный ключ: {0}")]
+    InvalidKey(#[from] ed25519_dalek::SignatureError),
+    #[error("Ошибка шифрования или неверный ключ")]
+    Cipher,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CellError {
+    #[error("Некорректный base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Некорректный BOC: {0}")]
+    InvalidBoc(String),
+    #[error("Переполнение ячейки: {bits} бит, {refs} ссылок")]
+    Overflow { bits: usize, refs: usize },
+    #[error("В ячейке не хватает данных: нужно еще {bits} бит")]
+    Underflow { bits: usize },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum AddressError {
+    #[error("Неверный формат адреса: {0}")]
+    InvalidFormat(String),
+    #[error("Неверный workchain: {0}")]
+    InvalidWorkchain(String),
+    #[error("Неверный hash адреса: {0}")]
+    InvalidHash(#[from] hex::FromHexError),
+    #[error("Неверная контрольная сумма адреса: {0}")]
+    InvalidChecksum(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ChainError {
+    #[error("Ошибка соединения с TON API: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("TON API отклонил запрос ({status}): {body}")]
+    Rejected { status: u16, body: String },
+    #[error("Get-метод {method} завершился с кодом {exit_code}")]
+    GetMethod { method: String, exit_code: i64 },
+    #[error("Неожиданный ответ get-метода: {0}")]
+    InvalidStack(String),
+    #[error("Сообщение ({id}) истекло (valid_until {valid_until}) и не будет принято")]
+    Expired { id: String, valid_until: u64 },
+    #[error("Не удалось подтвердить сообщение ({id}): {reason}. Результат неизвестен, проверьте кошелек перед повтором")]
+    Unconfirmed { id: String, reason: String },
+}
+
+impl ChainError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(_) => true,
+            Self::Rejected { status, .. } => *status == 429 || *status >= 500,
+            // Истекшее сообщение точно не списало средства, его можно отправить заново
+            Self::Expired { .. } => true,
+            // Сообщение могло быть принято: повтор рискует двойной оплатой
+            Self::Unconfirmed { .. } => false,
+            Self::GetMethod { .. } | Self::InvalidStack(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ConfigError {
+    #[error("Недопустимый параметр: {0}")]
+    InvalidParameter(String),
+    #[error("Хранилище сессии: {0}")]
+    SessionStore(String),
+    #[error("Ошибка ввода-вывода: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Недопустимый режим отправки {mode}: {reason}")]
+    InvalidSendMode { mode: u8, reason: &'static str },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum VerificationError {
+    #[error("Некорректная сумма от Fragment: {0}")]
+    InvalidAmount(String),
+    #[error("Цена {price:.4} TON превышает лимит {limit:.4} TON")]
+    PriceLimitExceeded { price: f64, limit: f64 },
+    #[error("Комментарий транзакции не соответствует покупке: ожидалось \"{expected}\", получено \"{actual}\"")]
+    PayloadMismatch { expected: String, actual: String },
+    #[error("Fragment запросил оплату на {actual} вместо {expected}")]
+    UnexpectedDestination { expected: String, actual: String },
+    #[error("NFT {nft} принадлежит не нашему кошельку, а {owner}")]
+    NotOwner { nft: String, owner: String },
+    #[error("Недостаточно средств: на балансе {balance}, требуется {amount}")]
+    InsufficientBalance { balance: u128, amount: u128 },
+}
+
+// TELEGRAM USERNAME
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+enum UsernameError {
+    #[error("Пустой username")]
+    Empty,
+    #[error("Username слишком короткий: {0} символов (минимум {USERNAME_MIN_LEN})")]
+    TooShort(usize),
+    #[error("Username слишком длинный: {0} символов (максимум {USERNAME_MAX_LEN})")]
+    TooLong(usize),
+    #[error("Недопустимый символ в username: {0:?}")]
+    InvalidChar(char),
+    #[error("Username должен начинаться с буквы, а не с {0:?}")]
+    InvalidStart(char),
+    #[error("Username не может заканчиваться на '_'")]
+    TrailingUnderscore,
+}
+
+// Проверенный username без '@', пригодный для запросов к Fragment
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TelegramUsername(String);
+
+impl TelegramUsername {
+    // Принимает "@name", "name", "t.me/name" и "https://t.me/name"
+    fn parse(input: &str) -> std::result::Result<Self, UsernameError> {
+        let mut name = input.trim();
+
+        for prefix in ["https://", "http://"] {
+            if let Some(rest) = name.strip_prefix(prefix) {
+                name = rest;
+            }
+        }
+        for prefix in ["www.", "t.me/", "telegram.me/"] {
+            if let Some(rest) = name.strip_prefix(prefix) {
+                name = rest;
+            }
+        }
+        name = name.strip_prefix('@').unwrap_or(name);
+        name = name.split(['/', '?']).next().unwrap_or(name);
+
+        let first = name.chars().next().ok_or(UsernameError::Empty)?;
+        if let Some(c) = name.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '_') {
+            return Err(UsernameError::InvalidChar(c));
+        }
+        if !first.is_ascii_alphabetic() {
+            return Err(UsernameError::InvalidStart(first));
+        }
+
+        let len = name.len();
+        if len < USERNAME_MIN_LEN {
+            return Err(UsernameError::TooShort(len));
+        }
+        if len > USERNAME_MAX_LEN {
+            return Err(UsernameError::TooLong(len));
+        }
+        if name.ends_with('_') {
+            return Err(UsernameError::TrailingUnderscore);
+        }
+
+        Ok(Self(name.to_string()))
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for TelegramUsername {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}", self.0)
+    }
+}
+
 // ВСПОМОГАТЕЛЬНЫЕ ФУНКЦИИ
 fn get_cookies(data: &HashMap<&str, &str>) -> HashMap<String, String> {
     let mut cookies = HashMap::new();
@@ -111,12 +475,26 @@ fn fix_base64_padding(b64_string: &str) -> String {
     }
 }
 
-fn cookies_to_string(cookies: &HashMap<String, String>) -> String {
-    cookies
-        .iter()
-        .map(|(k, v)| format!("{}={}", k, v))
-        .collect::<Vec<_>>()
-        .join("; ")
+// Fragment отдает цену HTML-фрагментом: "<div class="... icon-ton">1.2345</div> ... ~&nbsp;$2.50"
+fn parse_quote(html: &str) -> Option<Quote> {
+    let usd_re = Regex::new(r"\$\s*([0-9][0-9,]*(?:\.[0-9]+)?)").unwrap();
+
+    let ton = parse_ton_value(html)?;
+    let usd = usd_re.captures(html)?.get(1)?.as_str().replace(',', "").parse().ok()?;
+
+    Some(Quote { ton, usd })
+}
+
+// hash Fragment API из конфига страницы: "apiUrl":"\/api?hash=ed3ec875a724358cea"
+fn parse_api_hash(html: &str) -> Option<String> {
+    let re = Regex::new(r"api\?hash=([0-9a-fA-F]+)").unwrap();
+    re.captures(html).map(|c| c[1].to_string())
+}
+
+// Payload для ton_proof, который Fragment кладет в конфиг страницы
+fn parse_ton_proof_payload(html: &str) -> Option<String> {
+    let re = Regex::new(r#""ton_?[pP]roof"\s*:\s*"([^"]+)""#).unwrap();
+    re.captures(html).map(|c| c[1].to_string())
 }
 
 // TON CRYPTO
@@ -126,19 +504,24 @@ impl TonCrypto {
     fn mnemonic_to_keys(mnemonic: &[String]) -> Result<(Vec<u8>, Vec<u8>)> {
         let mnemonic_str = mnemonic.join(" ");
         let salt = "TON default seed";
-        
+
+        // Энтропия: HMAC-SHA512 с мнемоникой в качестве ключа и пустым паролем
+        let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(mnemonic_str.as_bytes())
+            .map_err(|_| CryptoError::InvalidMnemonic)?;
+        mac.update(b"");
+        let entropy = mac.finalize().into_bytes();
+
         // PBKDF2 для получения seed
         let mut seed = [0u8; 64];
-        pbkdf2_hmac::<Hmac<Sha512>>(
-            mnemonic_str.as_bytes(),
+        pbkdf2_hmac::<Sha512>(
+            &entropy,
             salt.as_bytes(),
             100000,
             &mut seed,
         );
 
         // Генерируем ключевую пару Ed25519
-        let secret = SecretKey::from_bytes(&seed[..32])
-            .context("Ошибка создания секретного ключа")?;
+        let secret = SecretKey::from_bytes(&seed[..32]).map_err(CryptoError::from)?;
         
         let public = PublicKey::from(&secret);
 
@@ -146,468 +529,3832 @@ impl TonCrypto {
     }
 
     fn sign(message: &[u8], private_key: &[u8]) -> Result<Vec<u8>> {
-        let secret = SecretKey::from_bytes(private_key)
-            .context("Неверный приватный ключ")?;
+        let secret = SecretKey::from_bytes(private_key).map_err(CryptoError::from)?;
         let public = PublicKey::from(&secret);
         let keypair = Keypair { secret, public };
 
         let signature = keypair.sign(message);
         Ok(signature.to_bytes().to_vec())
     }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+        let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(key).expect("HMAC принимает ключ любой длины");
+        mac.update(data);
+        mac.finalize().into_bytes().into()
+    }
+
+    // Общий секрет X25519 из ключей Ed25519 (как ed2curve + nacl.scalarMult)
+    fn shared_key(private_key: &[u8], their_public_key: &[u8; 32]) -> Result<[u8; 32]> {
+        let hash = Sha512::digest(private_key);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        scalar[0] &= 248;
+        scalar[31] &= 127;
+        scalar[31] |= 64;
+
+        let point = CompressedEdwardsY(*their_public_key)
+            .decompress()
+            .ok_or(CryptoError::Cipher)?
+            .to_montgomery();
+
+        Ok((Scalar::from_bits(scalar) * point).to_bytes())
+    }
+
+    // Шифрование по схеме кошельков TON: случайный префикс (16..31 байт, первый байт — его длина),
+    // msg_key = HMAC-SHA512(salt, данные)[..16], ключ и IV AES-256-CBC из HMAC-SHA512(shared, msg_key)
+    fn encrypt_data(data: &[u8], shared_key: &[u8; 32], salt: &[u8]) -> Vec<u8> {
+        let prefix_len = ((16 + 15 + data.len()) & !15) - data.len();
+        let mut plain = vec![0u8; prefix_len];
+        OsRng.fill_bytes(&mut plain);
+        plain[0] = prefix_len as u8;
+        plain.extend_from_slice(data);
+
+        let msg_key = &Self::hmac_sha512(salt, &plain)[..16];
+        let x = Self::hmac_sha512(shared_key, msg_key);
+
+        let len = plain.len();
+        cbc::Encryptor::<aes::Aes256>::new(x[..32].into(), x[32..48].into())
+            .encrypt_padded_mut::<NoPadding>(&mut plain, len)
+            .expect("длина кратна блоку");
+
+        let mut result = msg_key.to_vec();
+        result.extend_from_slice(&plain);
+        result
+    }
+
+    fn decrypt_data(encrypted: &[u8], shared_key: &[u8; 32], salt: &[u8]) -> Result<Vec<u8>> {
+        if encrypted.len() < 32 || encrypted.len() % 16 != 0 {
+            return Err(CryptoError::Cipher.into());
+        }
+        let (msg_key, cipher_text) = encrypted.split_at(16);
+        let x = Self::hmac_sha512(shared_key, msg_key);
+
+        let mut plain = cipher_text.to_vec();
+        cbc::Decryptor::<aes::Aes256>::new(x[..32].into(), x[32..48].into())
+            .decrypt_padded_mut::<NoPadding>(&mut plain)
+            .map_err(|_| CryptoError::Cipher)?;
+
+        // Неверный ключ или соль дают другой msg_key
+        if Self::hmac_sha512(salt, &plain)[..16] != *msg_key {
+            return Err(CryptoError::Cipher.into());
+        }
+
+        let prefix_len = plain[0] as usize;
+        if prefix_len < 16 || prefix_len > plain.len() {
+            return Err(CryptoError::Cipher.into());
+        }
+        Ok(plain[prefix_len..].to_vec())
+    }
+}
+
+// TON ADDRESS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TonAddress {
+    workchain: i8,
+    hash: [u8; 32],
+}
+
+impl TonAddress {
+    // Принимает raw-формат "0:hex" и user-friendly "EQ..."/"UQ..." (base64 и base64url)
+    fn parse(address: &str) -> std::result::Result<Self, AddressError> {
+        if let Some((workchain, hash)) = address.split_once(':') {
+            let workchain: i8 = workchain
+                .parse()
+                .map_err(|_| AddressError::InvalidWorkchain(workchain.to_string()))?;
+            let hash: [u8; 32] = hex::decode(hash)?
+                .try_into()
+                .map_err(|_| AddressError::InvalidFormat(address.to_string()))?;
+
+            return Ok(Self { workchain, hash });
+        }
+
+        let normalized = address.replace('-', "+").replace('_', "/");
+        let bytes = general_purpose::STANDARD
+            .decode(&normalized)
+            .map_err(|_| AddressError::InvalidFormat(address.to_string()))?;
+        if bytes.len() != 36 {
+            return Err(AddressError::InvalidFormat(address.to_string()));
+        }
+
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&bytes[..34]);
+        if crc.to_be_bytes() != bytes[34..36] {
+            return Err(AddressError::InvalidChecksum(address.to_string()));
+        }
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&bytes[2..34]);
+
+        Ok(Self { workchain: bytes[1] as i8, hash })
+    }
+
+    fn to_raw(&self) -> String {
+        format!("{}:{}", self.workchain, hex::encode(self.hash))
+    }
+
+    // Флаг bounce из user-friendly формата ("UQ..." — non-bounceable). Raw-адрес считается bounceable
+    fn is_bounceable(address: &str) -> bool {
+        if address.contains(':') {
+            return true;
+        }
+        general_purpose::URL_SAFE
+            .decode(address.replace('+', "-").replace('/', "_"))
+            .map(|bytes| bytes.first().map_or(true, |tag| tag & 0x40 == 0))
+            .unwrap_or(true)
+    }
+
+    fn to_friendly(&self, bounceable: bool, testnet: bool) -> String {
+        let mut tag = if bounceable { 0x11 } else { 0x51 };
+        if testnet {
+            tag |= 0x80;
+        }
+
+        let mut bytes = Vec::with_capacity(36);
+        bytes.push(tag);
+        bytes.push(self.workchain as u8);
+        bytes.extend_from_slice(&self.hash);
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&bytes);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+
+        general_purpose::URL_SAFE.encode(&bytes)
+    }
+}
+
+impl std::fmt::Display for TonAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_raw())
+    }
 }
 
-// TON CELL (упрощенная реализация)
+// TON CELL
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct TonCell {
     data: Vec<u8>,
+    bit_len: usize,
     refs: Vec<TonCell>,
 }
 
 impl TonCell {
+    const MAX_BITS: usize = 1023;
+    const MAX_REFS: usize = 4;
+
     fn new() -> Self {
         Self {
             data: Vec::new(),
+            bit_len: 0,
             refs: Vec::new(),
         }
     }
 
     fn from_data(data: Vec<u8>) -> Self {
         Self {
+            bit_len: data.len() * 8,
             data,
             refs: Vec::new(),
         }
     }
 
+    fn write_bit(&mut self, bit: bool) {
+        if self.bit_len % 8 == 0 {
+            self.data.push(0);
+        }
+        if bit {
+            self.data[self.bit_len / 8] |= 0x80 >> (self.bit_len % 8);
+        }
+        self.bit_len += 1;
+    }
+
     fn write_uint(&mut self, value: u64, bits: usize) {
-        let bytes = (bits + 7) / 8;
-        let value_bytes = value.to_be_bytes();
-        let start = 8 - bytes;
-        self.data.extend_from_slice(&value_bytes[start..]);
+        for i in (0..bits).rev() {
+            self.write_bit(i < 64 && (value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_int(&mut self, value: i64, bits: usize) {
+        for i in (0..bits).rev() {
+            self.write_bit((value >> i.min(63)) & 1 == 1);
+        }
+    }
+
+    // Coins (VarUInteger 16): 4 бита длины в байтах, затем само значение
+    fn write_coins(&mut self, amount: u128) {
+        let len = (128 - amount.leading_zeros() as usize + 7) / 8;
+        self.write_uint(len as u64, 4);
+        for byte in &amount.to_be_bytes()[16 - len..] {
+            self.write_uint(*byte as u64, 8);
+        }
     }
 
     fn write_bytes(&mut self, bytes: &[u8]) {
-        self.data.extend_from_slice(bytes);
+        for byte in bytes {
+            self.write_uint(*byte as u64, 8);
+        }
     }
 
     fn write_address(&mut self, address: &str) -> Result<()> {
-        // Парсим адрес вида "0:hash"
-        let parts: Vec<&str> = address.split(':').collect();
-        if parts.len() != 2 {
-            return Err(anyhow::anyhow!("Неверный формат адреса"));
-        }
+        let address = TonAddress::parse(address)?;
+        self.write_ton_address(&address);
+        Ok(())
+    }
 
-        let workchain: i8 = parts[0].parse()?;
-        let hash = hex::decode(parts[1])?;
+    // addr_std$10 anycast:(Maybe Anycast) workchain_id:int8 address:bits256
+    fn write_ton_address(&mut self, address: &TonAddress) {
+        self.write_uint(0b10, 2);
+        self.write_bit(false);
+        self.write_int(address.workchain as i64, 8);
+        self.write_bytes(&address.hash);
+    }
 
-        self.data.push(0x01); // addr_std
-        self.data.push(workchain as u8);
-        self.data.extend_from_slice(&hash);
+    // addr_none$00
+    fn write_address_none(&mut self) {
+        self.write_uint(0, 2);
+    }
 
-        Ok(())
+    // Дописывает биты и ссылки другой ячейки в текущую
+    fn append(&mut self, other: &TonCell) {
+        for i in 0..other.bit_len {
+            self.write_bit(other.data[i / 8] & (0x80 >> (i % 8)) != 0);
+        }
+        self.refs.extend(other.refs.iter().cloned());
     }
 
     fn add_ref(&mut self, cell: TonCell) {
         self.refs.push(cell);
     }
 
-    fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::new();
-        
-        // Дескрипторы
+    fn validate(&self) -> std::result::Result<(), CellError> {
+        if self.bit_len > Self::MAX_BITS || self.refs.len() > Self::MAX_REFS {
+            return Err(CellError::Overflow { bits: self.bit_len, refs: self.refs.len() });
+        }
+        self.refs.iter().try_for_each(TonCell::validate)
+    }
+
+    // Дескрипторы d1, d2 и данные с битом завершения
+    fn descriptors_and_data(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(2 + self.data.len());
         result.push(self.refs.len() as u8);
-        result.push(self.data.len() as u8);
-        
-        // Данные
-        result.extend_from_slice(&self.data);
-        
-        // Ссылки
-        for ref_cell in &self.refs {
-            result.extend_from_slice(&ref_cell.serialize());
+        result.push((self.bit_len / 8 + (self.bit_len + 7) / 8) as u8);
+
+        let mut data = self.data.clone();
+        if self.bit_len % 8 != 0 {
+            data[self.bit_len / 8] |= 0x80 >> (self.bit_len % 8);
         }
-        
+        result.extend_from_slice(&data);
+
         result
     }
 
-    fn hash(&self) -> Vec<u8> {
-        let serialized = self.serialize();
-        let mut hasher = Sha256::new();
-        hasher.update(&serialized);
-        hasher.finalize().to_vec()
+    fn depth(&self) -> u16 {
+        self.refs.iter().map(|r| r.depth() + 1).max().unwrap_or(0)
     }
 
-    fn to_boc(&self) -> String {
-        let serialized = self.serialize();
-        general_purpose::STANDARD.encode(&serialized)
+    fn hash(&self) -> [u8; 32] {
+        let mut repr = self.descriptors_and_data();
+        for ref_cell in &self.refs {
+            repr.extend_from_slice(&ref_cell.depth().to_be_bytes());
+        }
+        for ref_cell in &self.refs {
+            repr.extend_from_slice(&ref_cell.hash());
+        }
+        TonCrypto::sha256(&repr)
     }
 
-    fn from_boc(boc: &str) -> Result<Self> {
-        let fixed = fix_base64_padding(boc);
-        let data = general_purpose::STANDARD.decode(&fixed)?;
-        Ok(Self::from_data(data))
+    fn to_boc(&self) -> Result<String> {
+        Ok(general_purpose::STANDARD.encode(self.to_boc_bytes()?))
     }
-}
 
-// FRAGMENT CLIENT
-struct FragmentClient {
-    url: String,
-    cookies: HashMap<String, String>,
-    client: Client,
-}
+    // Сериализация в BOC с CRC32C: ячейки в топологическом порядке, одинаковые ячейки не дублируются
+    fn to_boc_bytes(&self) -> Result<Vec<u8>> {
+        self.validate()?;
 
-impl FragmentClient {
-    fn new(fragment_hash: &str, cookies_data: &HashMap<&str, &str>) -> Self {
-        Self {
-            url: format!("https://fragment.com/api?hash={}", fragment_hash),
-            cookies: get_cookies(cookies_data),
-            client: Client::builder()
-                .timeout(Duration::from_secs(30))
-                .build()
-                .unwrap(),
+        fn visit<'a>(
+            cell: &'a TonCell,
+            seen: &mut HashMap<[u8; 32], ()>,
+            order: &mut Vec<(&'a TonCell, [u8; 32])>,
+        ) {
+            let hash = cell.hash();
+            if seen.contains_key(&hash) {
+                return;
+            }
+            seen.insert(hash, ());
+            for ref_cell in &cell.refs {
+                visit(ref_cell, seen, order);
+            }
+            order.push((cell, hash));
         }
-    }
-
-    async fn fetch_recipient(&self, query: &str) -> Result<String> {
-        let mut params = HashMap::new();
-        params.insert("query", query);
-        params.insert("method", "searchStarsRecipient");
 
-        let response = self.client
-            .post(&self.url)
-            .header(header::COOKIE, cookies_to_string(&self.cookies))
-            .form(&params)
-            .send()
-            .await?;
+        let mut order = Vec::new();
+        visit(self, &mut HashMap::new(), &mut order);
+        order.reverse();
 
-        let body = response.text().await?;
-        println!("Recipient search: {}", body);
+        let index: HashMap<[u8; 32], usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, (_, hash))| (*hash, i))
+            .collect();
 
-        let result: RecipientResponse = serde_json::from_str(&body)?;
-        
-        result.found
-            .and_then(|f| Some(f.recipient))
-            .ok_or_else(|| anyhow::anyhow!("Получатель не найден"))
-    }
+        let size_bytes = bytes_for(order.len() as u64);
+        let mut cells_data = Vec::new();
+        for (cell, _) in &order {
+            cells_data.extend_from_slice(&cell.descriptors_and_data());
+            for ref_cell in &cell.refs {
+                let i = index[&ref_cell.hash()] as u64;
+                cells_data.extend_from_slice(&i.to_be_bytes()[8 - size_bytes..]);
+            }
+        }
+        let offset_bytes = bytes_for(cells_data.len() as u64);
 
-    async fn fetch_req_id(&self, recipient: &str, quantity: i32) -> Result<String> {
-        let mut params = HashMap::new();
-        params.insert("recipient", recipient.to_string());
-        params.insert("quantity", quantity.to_string());
-        params.insert("method", "initBuyStarsRequest".to_string());
+        let mut boc = vec![0xb5, 0xee, 0x9c, 0x72];
+        boc.push(0x40 | size_bytes as u8); // has_crc32c
+        boc.push(offset_bytes as u8);
+        boc.extend_from_slice(&(order.len() as u64).to_be_bytes()[8 - size_bytes..]);
+        boc.extend_from_slice(&1u64.to_be_bytes()[8 - size_bytes..]); // roots
+        boc.extend_from_slice(&0u64.to_be_bytes()[8 - size_bytes..]); // absent
+        boc.extend_from_slice(&(cells_data.len() as u64).to_be_bytes()[8 - offset_bytes..]);
+        boc.extend_from_slice(&0u64.to_be_bytes()[8 - size_bytes..]); // root index
+        boc.extend_from_slice(&cells_data);
 
-        let response = self.client
-            .post(&self.url)
-            .header(header::COOKIE, cookies_to_string(&self.cookies))
-            .form(&params)
-            .send()
-            .await?;
+        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI).checksum(&boc);
+        boc.extend_from_slice(&crc.to_le_bytes());
 
-        let body = response.text().await?;
-        println!("Request ID: {}", body);
+        Ok(boc)
+    }
 
-        let result: ReqIdResponse = serde_json::from_str(&body)?;
-        
-        result.req_id
-            .ok_or_else(|| anyhow::anyhow!("Не удалось создать запрос"))
+    fn from_boc(boc: &str) -> Result<Self> {
+        let fixed = fix_base64_padding(&boc.replace('-', "+").replace('_', "/"));
+        let data = general_purpose::STANDARD.decode(&fixed).map_err(CellError::from)?;
+        Ok(Self::from_boc_bytes(&data)?)
     }
 
-    async fn fetch_buy_link(&self, recipient: &str, req_id: &str, quantity: i32) -> Result<(String, String, String)> {
-        let features = json!([
-            "SendTransaction",
-            {"name": "SendTransaction", "maxMessages": 255}
-        ]);
+    fn from_boc_bytes(boc: &[u8]) -> std::result::Result<Self, CellError> {
+        let invalid = |reason: &str| CellError::InvalidBoc(reason.to_string());
+        let mut reader = ByteReader { data: boc, pos: 0 };
 
-        let mut params = HashMap::new();
-        params.insert("address", FRAGMENT_ADDRESS.to_string());
-        params.insert("chain", "-239".to_string());
-        params.insert("walletStateInit", FRAGMENT_WALLETS.to_string());
-        params.insert("publicKey", FRAGMENT_PUBLICKEY.to_string());
-        params.insert("features", features.to_string());
-        params.insert("maxProtocolVersion", "2".to_string());
-        params.insert("platform", "iphone".to_string());
-        params.insert("appName", "Tonkeeper".to_string());
-        params.insert("appVersion", "5.0.14".to_string());
-        params.insert("transaction", "1".to_string());
-        params.insert("id", req_id.to_string());
-        params.insert("show_sender", "0".to_string());
-        params.insert("method", "getBuyStarsLink".to_string());
+        if reader.take(4).ok_or_else(|| invalid("пустой BOC"))? != [0xb5, 0xee, 0x9c, 0x72] {
+            return Err(invalid("неизвестный формат"));
+        }
 
-        let response = self.client
-            .post(&self.url)
-            .header(header::ACCEPT, "application/json, text/javascript, */*; q=0.01")
-            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded; charset=UTF-8")
-            .header("Origin", "https://fragment.com")
-            .header("Referer", format!("https://fragment.com/stars/buy?recipient={}&quantity={}", recipient, quantity))
-            .header("User-Agent", "Mozilla/5.0 (iPhone; CPU iPhone OS 16_6 like Mac OS X) AppleWebKit/605.1.15")
-            .header("X-Requested-With", "XMLHttpRequest")
-            .header(header::COOKIE, cookies_to_string(&self.cookies))
-            .form(&params)
-            .send()
-            .await?;
+        let flags = reader.uint(1).ok_or_else(|| invalid("нет заголовка"))?;
+        let has_idx = flags & 0x80 != 0;
+        let size_bytes = (flags & 0x07) as usize;
+        let offset_bytes = reader.uint(1).ok_or_else(|| invalid("нет заголовка"))? as usize;
+        if !(1..=4).contains(&size_bytes) || !(1..=8).contains(&offset_bytes) {
+            return Err(invalid("неверный размер полей заголовка"));
+        }
 
-        let body = response.text().await?;
-        println!("Buy link: {}", body);
+        let cells = reader.uint(size_bytes).ok_or_else(|| invalid("нет заголовка"))? as usize;
+        let roots = reader.uint(size_bytes).ok_or_else(|| invalid("нет заголовка"))? as usize;
+        reader.uint(size_bytes).ok_or_else(|| invalid("нет заголовка"))?; // absent
+        reader.uint(offset_bytes).ok_or_else(|| invalid("нет заголовка"))?; // tot_cells_size
+        // Число ячеек берется из заголовка: проверяем его до выделения памяти,
+        // каждая ячейка занимает в BOC хотя бы 2 байта дескрипторов
+        if cells == 0 || cells > boc.len() / 2 || roots > cells {
+            return Err(invalid("неверное число ячеек"));
+        }
+        if roots != 1 {
+            return Err(invalid("поддерживается только один корень"));
+        }
+        let root = reader.uint(size_bytes).ok_or_else(|| invalid("нет корня"))? as usize;
+        if root >= cells {
+            return Err(invalid("неверный индекс корня"));
+        }
+        if has_idx {
+            reader.take(cells * offset_bytes).ok_or_else(|| invalid("нет индекса"))?;
+        }
 
-        let result: BuyLinkResponse = serde_json::from_str(&body)?;
+        let mut raw = Vec::with_capacity(cells);
+        for _ in 0..cells {
+            let d1 = reader.uint(1).ok_or_else(|| invalid("обрезанная ячейка"))?;
+            let d2 = reader.uint(1).ok_or_else(|| invalid("обрезанная ячейка"))?;
+            if d1 & 0x08 != 0 {
+                return Err(invalid("exotic-ячейки не поддерживаются"));
+            }
+            if d1 & 0x10 != 0 {
+                let hashes = ((d1 >> 5) & 0x07).count_ones() as usize + 1;
+                reader.take(hashes * 34).ok_or_else(|| invalid("обрезанная ячейка"))?;
+            }
 
-        if let Some(true) = result.ok {
-            if let Some(transaction) = result.transaction {
-                if let Some(msg) = transaction.messages.first() {
-                    return Ok((msg.address.clone(), msg.amount.clone(), msg.payload.clone()));
+            let data = reader
+                .take(((d2 + 1) / 2) as usize)
+                .ok_or_else(|| invalid("обрезанная ячейка"))?
+                .to_vec();
+            let mut refs = Vec::new();
+            for _ in 0..(d1 & 0x07) {
+                refs.push(reader.uint(size_bytes).ok_or_else(|| invalid("обрезанная ячейка"))? as usize);
+            }
+
+            // Нечетный d2 означает неполный последний байт с битом завершения
+            let mut bit_len = data.len() * 8;
+            let mut data = data;
+            if d2 % 2 == 1 {
+                let last = data.last().copied().ok_or_else(|| invalid("нет бита завершения"))?;
+                if last == 0 {
+                    return Err(invalid("нет бита завершения"));
+                }
+                let trailing = last.trailing_zeros() as usize;
+                bit_len -= trailing + 1;
+                let last_index = data.len() - 1;
+                data[last_index] &= !(1u8 << trailing);
+                if bit_len % 8 == 0 {
+                    data.pop();
                 }
             }
+            raw.push((data, bit_len, refs));
         }
 
-        Err(anyhow::anyhow!("Не удалось получить данные транзакции"))
+        // Ссылки всегда указывают на ячейки с большим индексом, поэтому собираем с конца
+        let mut built: Vec<Option<TonCell>> = vec![None; cells];
+        for i in (0..cells).rev() {
+            let (data, bit_len, ref_indices) = &raw[i];
+            let mut refs = Vec::with_capacity(ref_indices.len());
+            for &r in ref_indices {
+                let child = built
+                    .get(r)
+                    .and_then(|c| c.clone())
+                    .ok_or_else(|| invalid("неверная ссылка"))?;
+                refs.push(child);
+            }
+            built[i] = Some(TonCell { data: data.clone(), bit_len: *bit_len, refs });
+        }
+
+        built
+            .get_mut(root)
+            .and_then(Option::take)
+            .ok_or_else(|| invalid("неверный индекс корня"))
     }
 }
 
-// TON WALLET
-struct TonWallet {
-    public_key: Vec<u8>,
-    private_key: Vec<u8>,
-    workchain: i8,
-    wallet_id: u32,
+// Последовательное чтение битов ячейки (ответы get-методов, payload)
+struct CellSlice<'a> {
+    cell: &'a TonCell,
+    pos: usize,
+    ref_pos: usize,
 }
 
-impl TonWallet {
-    fn new(mnemonic: &[String], workchain: i8) -> Result<Self> {
-        let (public_key, private_key) = TonCrypto::mnemonic_to_keys(mnemonic)?;
-        
-        Ok(Self {
-            public_key,
-            private_key,
-            workchain,
-            wallet_id: 698983191, // Wallet V4R2
+impl<'a> CellSlice<'a> {
+    fn new(cell: &'a TonCell) -> Self {
+        Self { cell, pos: 0, ref_pos: 0 }
+    }
+
+    fn remaining_bits(&self) -> usize {
+        self.cell.bit_len - self.pos
+    }
+
+    fn read_bit(&mut self) -> std::result::Result<bool, CellError> {
+        if self.pos >= self.cell.bit_len {
+            return Err(CellError::Underflow { bits: 1 });
+        }
+        let bit = self.cell.data[self.pos / 8] & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    fn read_uint(&mut self, bits: usize) -> std::result::Result<u64, CellError> {
+        if self.remaining_bits() < bits {
+            return Err(CellError::Underflow { bits });
+        }
+        let mut value = 0u64;
+        for _ in 0..bits {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    fn read_int(&mut self, bits: usize) -> std::result::Result<i64, CellError> {
+        let value = self.read_uint(bits)?;
+        if bits < 64 && value & (1 << (bits - 1)) != 0 {
+            return Ok(value as i64 - (1i64 << bits));
+        }
+        Ok(value as i64)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> std::result::Result<Vec<u8>, CellError> {
+        if self.remaining_bits() < len * 8 {
+            return Err(CellError::Underflow { bits: len * 8 });
+        }
+        (0..len).map(|_| self.read_uint(8).map(|b| b as u8)).collect()
+    }
+
+    // addr_std или addr_none (None)
+    fn read_address(&mut self) -> std::result::Result<Option<TonAddress>, CellError> {
+        match self.read_uint(2)? {
+            0b00 => Ok(None),
+            0b10 => {
+                if self.read_bit()? {
+                    return Err(CellError::InvalidBoc("anycast-адреса не поддерживаются".to_string()));
+                }
+                let workchain = self.read_int(8)? as i8;
+                let hash = self.read_bytes(32)?.try_into().unwrap();
+                Ok(Some(TonAddress { workchain, hash }))
+            }
+            tag => Err(CellError::InvalidBoc(format!("неподдерживаемый тип адреса: {:02b}", tag))),
+        }
+    }
+
+    fn read_ref(&mut self) -> std::result::Result<&'a TonCell, CellError> {
+        let cell = self.cell.refs.get(self.ref_pos).ok_or(CellError::Underflow { bits: 0 })?;
+        self.ref_pos += 1;
+        Ok(cell)
+    }
+
+    // Snake-данные: байты этой ячейки, затем продолжение по первой ссылке
+    fn read_snake_bytes(&mut self) -> std::result::Result<Vec<u8>, CellError> {
+        let mut bytes = self.read_bytes(self.remaining_bits() / 8)?;
+
+        let mut next = self.cell.refs.get(self.ref_pos);
+        while let Some(cell) = next {
+            let mut slice = CellSlice::new(cell);
+            bytes.extend(slice.read_bytes(cell.bit_len / 8)?);
+            next = cell.refs.first();
+        }
+
+        Ok(bytes)
+    }
+}
+
+// Последовательное чтение байтов BOC
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        Some(self.take(len)?.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+}
+
+// toncenter отдает большие числа то строкой, то числом
+fn deserialize_u64_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<u64, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+        Value::Number(n) => n.as_u64().ok_or_else(|| serde::de::Error::custom("отрицательное число")),
+        other => Err(serde::de::Error::custom(format!("ожидалось число, получено {}", other))),
+    }
+}
+
+// Ячейка из элемента стека toncenter: ["cell", {"bytes": "te6..."}] или ["slice", {...}]
+fn stack_cell(entry: Option<&Value>) -> Result<TonCell> {
+    let bytes = entry
+        .and_then(|e| e.get(1))
+        .and_then(|e| e.get("bytes"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| ChainError::InvalidStack(format!("ожидалась ячейка, получено {:?}", entry)))?;
+
+    TonCell::from_boc(bytes)
+}
+
+// Минимальное количество байтов для записи числа (не меньше одного)
+fn bytes_for(value: u64) -> usize {
+    ((64 - value.leading_zeros() as usize + 7) / 8).max(1)
+}
+
+// FRAGMENT ERRORS
+#[derive(Debug, thiserror::Error)]
+enum FragmentError {
+    #[error("Не найдено: {message}")]
+    NotFound { message: String, body: String },
+    #[error("Сессия Fragment истекла: {message}")]
+    SessionExpired { message: String, body: String },
+    #[error("Кошелек не подключен к Fragment: {message}")]
+    WalletNotConnected { message: String, body: String },
+    #[error("Слишком много запросов к Fragment: {message}")]
+    RateLimited { message: String, body: String },
+    #[error("Недопустимое количество: {message}")]
+    InsufficientQuantity { message: String, body: String },
+    #[error("Неверный hash Fragment API: {message}")]
+    InvalidHash { message: String, body: String },
+    #[error("Fragment вернул HTML вместо JSON")]
+    UnexpectedHtml { body: String },
+    #[error("Ошибка соединения с Fragment: {0}")]
+    Transport(#[from] reqwest::Error),
+    // Ошибка Fragment, для которой нет отдельного варианта
+    #[error("Ошибка Fragment: {message}")]
+    Api { message: String, body: String },
+}
+
+impl FragmentError {
+    // Так Fragment отвечает на устаревший hash. Сравнивается вся строка: "hash" встречается
+    // и в других ошибках (например, про hash транзакции), и на них hash обновлять не нужно
+    const INVALID_HASH: &str = "Invalid hash";
+
+    // Fragment возвращает ошибки строкой в поле error, тип определяем по тексту
+    fn from_message(message: &str, body: &str) -> Self {
+        let text = message.to_lowercase();
+        let is_invalid_hash = message.trim().eq_ignore_ascii_case(Self::INVALID_HASH);
+        let message = message.to_string();
+        let body = body.to_string();
+
+        if is_invalid_hash {
+            Self::InvalidHash { message, body }
+        } else if text.contains("wallet") && text.contains("connect") {
+            Self::WalletNotConnected { message, body }
+        } else if text.contains("session") || text.contains("log in") || text.contains("login")
+            || text.contains("unauthorized") || text.contains("access denied")
+        {
+            Self::SessionExpired { message, body }
+        } else if text.contains("too many") || text.contains("flood") || text.contains("try again later") {
+            Self::RateLimited { message, body }
+        } else if text.contains("quantity") || text.contains("minimum") || text.contains("at least") {
+            Self::InsufficientQuantity { message, body }
+        } else if text.contains("not found") || text.contains("no telegram users") || text.contains("не найден") {
+            Self::NotFound { message, body }
+        } else {
+            Self::Api { message, body }
+        }
+    }
+
+    // Сырой ответ Fragment, если он был получен
+    fn body(&self) -> Option<&str> {
+        match self {
+            Self::NotFound { body, .. }
+            | Self::SessionExpired { body, .. }
+            | Self::WalletNotConnected { body, .. }
+            | Self::RateLimited { body, .. }
+            | Self::InsufficientQuantity { body, .. }
+            | Self::InvalidHash { body, .. }
+            | Self::UnexpectedHtml { body }
+            | Self::Api { body, .. } => Some(body),
+            Self::Transport(_) => None,
+        }
+    }
+
+    fn is_session_expired(&self) -> bool {
+        matches!(self, Self::SessionExpired { .. })
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited { .. } | Self::Transport(_))
+    }
+}
+
+// DEVICE PROFILE
+// Кошелек, которым клиент представляется Fragment через TON Connect
+#[derive(Debug, Clone)]
+struct DeviceProfile {
+    platform: String,
+    app_name: String,
+    app_version: String,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            platform: "iphone".to_string(),
+            app_name: "Tonkeeper".to_string(),
+            app_version: "5.0.14".to_string(),
+        }
+    }
+}
+
+impl DeviceProfile {
+    fn features(max_messages: usize) -> Value {
+        json!([
+            "SendTransaction",
+            {"name": "SendTransaction", "maxMessages": max_messages}
+        ])
+    }
+
+    fn to_json(&self, max_messages: usize) -> Value {
+        json!({
+            "platform": self.platform,
+            "appName": self.app_name,
+            "appVersion": self.app_version,
+            "maxProtocolVersion": 2,
+            "features": Self::features(max_messages),
         })
     }
+}
 
-    fn get_address(&self) -> String {
-        // Упрощенный расчет адреса
-        let mut hasher = Sha256::new();
-        hasher.update(&self.public_key);
-        let hash = hasher.finalize();
-        
-        format!("{}:{}", self.workchain, hex::encode(&hash[..]))
+// COOKIE STORE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredCookie {
+    value: String,
+    // Unix-время истечения; None — сессионная cookie
+    expires_at: Option<i64>,
+}
+
+// Cookies сессии Fragment: обновляются из Set-Cookie и хранятся на диске в зашифрованном виде
+struct CookieStore {
+    cookies: HashMap<String, StoredCookie>,
+    path: Option<PathBuf>,
+    key: [u8; 32],
+    // Соль PBKDF2, записывается в начало файла
+    salt: [u8; 16],
+}
+
+impl CookieStore {
+    const KDF_ROUNDS: u32 = 100_000;
+
+    fn new(cookies: HashMap<String, String>) -> Self {
+        Self {
+            cookies: cookies
+                .into_iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(name, value)| (name, StoredCookie { value, expires_at: None }))
+                .collect(),
+            path: None,
+            key: [0u8; 32],
+            salt: [0u8; 16],
+        }
     }
 
-    fn create_transfer_message(
-        &self,
-        dest_address: &str,
-        amount: u64,
-        payload: &TonCell,
-        seqno: u32,
-    ) -> Result<TonCell> {
-        // Создаем внутреннее сообщение
-        let mut msg_body = TonCell::new();
-        msg_body.write_uint(0, 1); // int_msg_info
-        msg_body.write_uint(1, 1); // ihr_disabled
-        msg_body.write_uint(1, 1); // bounce
-        msg_body.write_uint(0, 1); // bounced
-        msg_body.write_uint(0, 2); // src addr_none
-        
-        msg_body.write_address(dest_address)?;
-        msg_body.write_uint(amount, 128);
-        msg_body.write_uint(0, 1); // extra currencies
-        msg_body.write_uint(0, 4); // ihr_fee
-        msg_body.write_uint(0, 4); // fwd_fee
-        msg_body.write_uint(0, 64); // created_lt
-        msg_body.write_uint(0, 32); // created_at
-        msg_body.write_uint(0, 1); // init
-        msg_body.write_uint(1, 1); // body present
-        
-        // Создаем внешнее сообщение
-        let mut ext_msg = TonCell::new();
-        ext_msg.write_uint(seqno as u64, 32);
-        ext_msg.write_uint((chrono::Utc::now().timestamp() + 60) as u64, 32); // valid_until
-        ext_msg.write_uint(self.wallet_id as u64, 32);
-        ext_msg.write_uint(3, 8); // mode
+    fn derive_key(secret: &str, salt: &[u8]) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        pbkdf2_hmac::<Sha256>(secret.as_bytes(), salt, Self::KDF_ROUNDS, &mut key);
+        key
+    }
 
-        Ok(ext_msg)
+    // Секрет из примера конфигурации знает любой, кто читал исходники
+    fn secret_is_set(secret: &str) -> bool {
+        !secret.is_empty() && !secret.starts_with("ваш_")
     }
 
-    fn sign_and_send(&self, message: &TonCell) -> Result<String> {
-        let msg_data = message.serialize();
-        let signature = TonCrypto::sign(&msg_data, &self.private_key)?;
-        
-        let mut signed_msg = TonCell::new();
-        signed_msg.write_bytes(&signature);
-        signed_msg.write_bytes(&msg_data);
-        
-        let boc = signed_msg.to_boc();
-        Ok(boc)
+    // Загружает сохраненную сессию; если файла еще нет, начинает с переданных cookies.
+    // Файл: соль (16 байт), nonce (12 байт), cookies в JSON, зашифрованные AES-256-GCM
+    fn open(path: impl Into<PathBuf>, secret: &str, initial: HashMap<String, String>) -> Result<Self> {
+        if !Self::secret_is_set(secret) {
+            return Err(ConfigError::SessionStore("не задан SESSION_SECRET".to_string()).into());
+        }
+        let path = path.into();
+        let mut store = Self::new(initial);
+
+        if path.exists() {
+            let encrypted = std::fs::read(&path).map_err(ConfigError::from)?;
+            if encrypted.len() < 16 + 12 {
+                return Err(ConfigError::SessionStore("файл сессии поврежден".to_string()).into());
+            }
+            let (salt, encrypted) = encrypted.split_at(16);
+            store.salt.copy_from_slice(salt);
+            store.key = Self::derive_key(secret, salt);
+
+            let cipher = Aes256Gcm::new(&store.key.into());
+            let plain = cipher
+                .decrypt(Nonce::from_slice(&encrypted[..12]), &encrypted[12..])
+                .map_err(|_| CryptoError::Cipher)?;
+            store.cookies = serde_json::from_slice(&plain)
+                .map_err(|e| ConfigError::SessionStore(e.to_string()))?;
+        } else {
+            OsRng.fill_bytes(&mut store.salt);
+            store.key = Self::derive_key(secret, &store.salt);
+        }
+
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let plain = serde_json::to_vec(&self.cookies)
+            .map_err(|e| ConfigError::SessionStore(e.to_string()))?;
+        let cipher = Aes256Gcm::new(&self.key.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = cipher
+            .encrypt(&nonce, plain.as_slice())
+            .map_err(|_| CryptoError::Cipher)?;
+
+        let mut data = self.salt.to_vec();
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&encrypted);
+        std::fs::write(path, data).map_err(ConfigError::from)?;
+
+        Ok(())
+    }
+
+    fn is_valid(&self, name: &str) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        self.cookies
+            .get(name)
+            .map(|c| c.expires_at.map_or(true, |expires| expires > now))
+            .unwrap_or(false)
+    }
+
+    // Сессия Fragment держится на stel_ssid и stel_token
+    fn session_expired(&self) -> bool {
+        !self.is_valid("stel_ssid") || !self.is_valid("stel_token")
+    }
+
+    fn header(&self) -> String {
+        self.cookies
+            .iter()
+            .filter(|(name, _)| self.is_valid(name))
+            .map(|(name, cookie)| format!("{}={}", name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn retain(&mut self, names: &[&str]) {
+        self.cookies.retain(|name, _| names.contains(&name.as_str()));
+    }
+
+    // Применяет Set-Cookie из ответа; возвращает true, если что-то изменилось
+    fn capture(&mut self, headers: &header::HeaderMap) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        let mut changed = false;
+
+        for value in headers.get_all(header::SET_COOKIE).iter() {
+            let Ok(value) = value.to_str() else { continue };
+            let mut parts = value.split(';');
+            let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+                continue;
+            };
+
+            let mut expires_at = None;
+            for attribute in parts {
+                let (key, val) = attribute.split_once('=').unwrap_or((attribute, ""));
+                match key.trim().to_lowercase().as_str() {
+                    "max-age" => expires_at = val.trim().parse::<i64>().ok().map(|age| now + age),
+                    "expires" if expires_at.is_none() => expires_at = parse_cookie_date(val.trim()),
+                    _ => {}
+                }
+            }
+
+            let name = name.trim().to_string();
+            let value = value.trim().to_string();
+            if value.is_empty() || value == "deleted" || expires_at.map_or(false, |e| e <= now) {
+                changed |= self.cookies.remove(&name).is_some();
+            } else {
+                self.cookies.insert(name, StoredCookie { value, expires_at });
+                changed = true;
+            }
+        }
+
+        changed
     }
 }
 
-// TON TRANSACTION
-struct TonTransaction {
-    wallet: TonWallet,
+// Дата из атрибута Expires: "Tue, 19 Nov 2026 10:00:00 GMT" или "Tue, 19-Nov-2026 10:00:00 GMT"
+fn parse_cookie_date(value: &str) -> Option<i64> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+        return Some(date.timestamp());
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%a, %d-%b-%Y %H:%M:%S GMT")
+        .ok()
+        .map(|date| date.and_utc().timestamp())
+}
+
+// FRAGMENT AUCTIONS
+// Коллекционные товары Fragment: username и анонимные номера
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CollectibleKind {
+    Username,
+    Number,
+}
+
+impl CollectibleKind {
+    // Тип для searchAuctions
+    fn search_type(self) -> &'static str {
+        match self {
+            CollectibleKind::Username => "usernames",
+            CollectibleKind::Number => "numbers",
+        }
+    }
+
+    // Страница товара: /username/durov, /number/88801234567
+    fn item_path(self, slug: &str) -> String {
+        match self {
+            CollectibleKind::Username => format!("/username/{}", slug),
+            CollectibleKind::Number => format!("/number/{}", slug),
+        }
+    }
+
+    fn from_collection(address: &TonAddress) -> Option<Self> {
+        [CollectibleKind::Username, CollectibleKind::Number]
+            .into_iter()
+            .find(|kind| TonAddress::parse(kind.collection_address()).ok().as_ref() == Some(address))
+    }
+
+    fn collection_address(self) -> &'static str {
+        match self {
+            CollectibleKind::Username => TELEGRAM_USERNAMES_COLLECTION,
+            CollectibleKind::Number => ANONYMOUS_NUMBERS_COLLECTION,
+        }
+    }
+
+    // Индекс NFT в коллекции — sha256 от имени товара без "@" и "+"
+    fn item_index(self, slug: &str) -> [u8; 32] {
+        TonCrypto::sha256(slug.as_bytes())
+    }
+
+    // Приводит "@name", "t.me/name" или "+888 0123 4567" к виду из адреса страницы
+    fn normalize_slug(self, input: &str) -> Result<String> {
+        match self {
+            CollectibleKind::Username => Ok(TelegramUsername::parse(input)?.as_str().to_string()),
+            CollectibleKind::Number => {
+                let digits: String = input
+                    .chars()
+                    .filter(|c| !matches!(c, '+' | ' ' | '-'))
+                    .collect();
+
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(ConfigError::InvalidParameter(format!("Некорректный номер: {}", input)).into());
+                }
+                Ok(digits)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuctionFilter {
+    All,
+    Auction,
+    Sale,
+    Sold,
+}
+
+impl AuctionFilter {
+    fn as_param(self) -> &'static str {
+        match self {
+            AuctionFilter::All => "",
+            AuctionFilter::Auction => "auction",
+            AuctionFilter::Sale => "sale",
+            AuctionFilter::Sold => "sold",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AuctionStatus {
+    OnAuction,
+    ForSale,
+    Sold,
+    Available,
+    Other(String),
+}
+
+impl AuctionStatus {
+    fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "on auction" => AuctionStatus::OnAuction,
+            "for sale" => AuctionStatus::ForSale,
+            "sold" => AuctionStatus::Sold,
+            "available" => AuctionStatus::Available,
+            _ => AuctionStatus::Other(text.trim().to_string()),
+        }
+    }
+}
+
+// Строка из списка аукционов
+#[derive(Debug, Clone)]
+struct AuctionLot {
+    kind: CollectibleKind,
+    slug: String,
+    name: String,
+    status: AuctionStatus,
+    price_ton: Option<f64>,
+    ends_at: Option<i64>,
+}
+
+// Страница списка: следующая загружается с next_offset
+#[derive(Debug, Clone)]
+struct AuctionPage {
+    lots: Vec<AuctionLot>,
+    next_offset: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct AuctionBid {
+    amount_ton: f64,
+    bidder: Option<String>,
+    placed_at: Option<i64>,
+}
+
+// Карточка товара со страницы /username/... или /number/...
+#[derive(Debug, Clone)]
+struct AuctionItem {
+    kind: CollectibleKind,
+    slug: String,
+    status: AuctionStatus,
+    highest_bid_ton: Option<f64>,
+    min_bid_ton: Option<f64>,
+    // Цена мгновенной покупки, если товар выставлен на продажу
+    price_ton: Option<f64>,
+    ends_at: Option<i64>,
+    owner: Option<String>,
+    history: Vec<AuctionBid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuctionSearchResponse {
+    html: Option<String>,
+    next_offset_id: Option<Value>,
+}
+
+// Сумма в TON из ячейки таблицы: "<div class="... icon-ton">1,250</div>"
+fn parse_ton_value(html: &str) -> Option<f64> {
+    let re = Regex::new(r"icon-ton[^>]*>\s*([0-9][0-9,]*(?:\.[0-9]+)?)").unwrap();
+    re.captures(html)?.get(1)?.as_str().replace(',', "").parse().ok()
+}
+
+// Время из тега <time datetime="2026-10-20T10:00:00+00:00">
+fn parse_datetime(html: &str) -> Option<i64> {
+    let re = Regex::new(r#"datetime="([^"]+)""#).unwrap();
+    let value = re.captures(html)?.get(1)?.as_str();
+    chrono::DateTime::parse_from_rfc3339(value).ok().map(|date| date.timestamp())
+}
+
+// Адрес кошелька из ссылки на обозреватель
+fn parse_wallet_link(html: &str) -> Option<String> {
+    let re = Regex::new(r"tonviewer\.com/([A-Za-z0-9_-]{48})").unwrap();
+    Some(re.captures(html)?.get(1)?.as_str().to_string())
+}
+
+fn strip_tags(html: &str) -> String {
+    let re = Regex::new(r"<[^>]+>").unwrap();
+    let text = re.replace_all(html, " ").replace("&nbsp;", " ");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Строки таблицы, которые Fragment отдает в списке аукционов и в searchAuctions
+fn parse_auction_list(html: &str) -> Vec<AuctionLot> {
+    let link_re = Regex::new(r#"href="/(username|number)/([^"?/]+)[^"]*""#).unwrap();
+    let name_re = Regex::new(r#"(?s)class="table-cell-value tm-value[^"]*"[^>]*>(.*?)</div>"#).unwrap();
+    let status_re = Regex::new(r#"(?s)class="[^"]*tm-status-[a-z]+[^"]*"[^>]*>(.*?)</"#).unwrap();
+
+    html.split("<tr")
+        .skip(1)
+        .filter_map(|row| {
+            let link = link_re.captures(row)?;
+            let kind = match &link[1] {
+                "username" => CollectibleKind::Username,
+                _ => CollectibleKind::Number,
+            };
+            let slug = link[2].to_string();
+
+            let name = name_re
+                .captures(row)
+                .map(|c| strip_tags(&c[1]))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| slug.clone());
+            let status = status_re
+                .captures(row)
+                .map(|c| AuctionStatus::parse(&strip_tags(&c[1])))
+                .unwrap_or(AuctionStatus::Other(String::new()));
+
+            Some(AuctionLot {
+                kind,
+                slug,
+                name,
+                status,
+                price_ton: parse_ton_value(row),
+                ends_at: parse_datetime(row),
+            })
+        })
+        .collect()
+}
+
+// Страница товара. None — если это не карточка аукциона (например, страница входа)
+fn parse_auction_item(html: &str, kind: CollectibleKind, slug: &str) -> Option<AuctionItem> {
+    let status_re = Regex::new(r#"(?s)class="tm-section-header-status[^"]*"[^>]*>(.*?)</"#).unwrap();
+    let status = AuctionStatus::parse(&strip_tags(&status_re.captures(html)?[1]));
+
+    // Таблица с условиями аукциона: "Highest Bid", "Minimum Bid", "Price"
+    let info_re = Regex::new(r"(?s)<tr[^>]*>\s*<th[^>]*>(.*?)</th>\s*<td[^>]*>(.*?)</td>").unwrap();
+    let mut highest_bid_ton = None;
+    let mut min_bid_ton = None;
+    let mut price_ton = None;
+    for row in info_re.captures_iter(html) {
+        let value = parse_ton_value(&row[2]);
+        match strip_tags(&row[1]).to_lowercase().as_str() {
+            "highest bid" => highest_bid_ton = value,
+            "minimum bid" => min_bid_ton = value,
+            "price" | "sale price" => price_ton = value,
+            _ => {}
+        }
+    }
+
+    let countdown_re = Regex::new(r#"(?s)class="[^"]*tm-(?:section-)?countdown[^"]*".*?(datetime="[^"]+")"#).unwrap();
+    let ends_at = countdown_re.captures(html).and_then(|c| parse_datetime(&c[1]));
+
+    let owner_re = Regex::new(r"(?s)>\s*Owner\s*<.*?(tonviewer\.com/[A-Za-z0-9_-]{48})").unwrap();
+    let owner = owner_re.captures(html).and_then(|c| parse_wallet_link(&c[1]));
+
+    // История ставок: сумма, кошелек и время в каждой строке
+    let history_re = Regex::new(r"(?s)Bid History.*?<tbody[^>]*>(.*?)</tbody>").unwrap();
+    let history = history_re
+        .captures(html)
+        .map(|c| {
+            c[1].split("<tr")
+                .skip(1)
+                .filter_map(|row| {
+                    Some(AuctionBid {
+                        amount_ton: parse_ton_value(row)?,
+                        bidder: parse_wallet_link(row),
+                        placed_at: parse_datetime(row),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(AuctionItem {
+        kind,
+        slug: slug.to_string(),
+        status,
+        highest_bid_ton,
+        min_bid_ton,
+        price_ton,
+        ends_at,
+        owner,
+        history,
+    })
+}
+
+// FRAGMENT CLIENT
+struct FragmentClient {
+    // Fragment периодически меняет hash, поэтому он обновляется на лету
+    hash: RwLock<String>,
+    cookies: Mutex<CookieStore>,
     client: Client,
+    // Кошелек для повторного входа, когда сессия истекает
+    session_wallet: Option<Box<dyn ConnectWallet>>,
+    device: DeviceProfile,
+}
+
+impl FragmentClient {
+    fn new(fragment_hash: &str, cookies_data: &HashMap<&str, &str>) -> Self {
+        Self::with_store(fragment_hash, CookieStore::new(get_cookies(cookies_data)))
+    }
+
+    // Сессия сохраняется в зашифрованный файл и переживает перезапуск сервиса
+    fn persistent(
+        fragment_hash: &str,
+        cookies_data: &HashMap<&str, &str>,
+        path: &str,
+        secret: &str,
+    ) -> Result<Self> {
+        let store = CookieStore::open(path, secret, get_cookies(cookies_data))?;
+        Ok(Self::with_store(fragment_hash, store))
+    }
+
+    fn with_store(fragment_hash: &str, store: CookieStore) -> Self {
+        Self {
+            hash: RwLock::new(fragment_hash.to_string()),
+            cookies: Mutex::new(store),
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+            session_wallet: None,
+            device: DeviceProfile::default(),
+        }
+    }
+
+    fn with_device(mut self, device: DeviceProfile) -> Self {
+        self.device = device;
+        self
+    }
+
+    // С кошельком клиент сам входит заново через TON Connect, когда сессия истекает
+    fn with_session_wallet(mut self, wallet: impl ConnectWallet + 'static) -> Self {
+        self.session_wallet = Some(Box::new(wallet));
+        self
+    }
+
+    fn cookie_header(&self) -> String {
+        self.cookies.lock().unwrap().header()
+    }
+
+    // Запоминает обновленные cookies из ответа и сохраняет их на диск
+    fn capture_cookies(&self, headers: &header::HeaderMap) {
+        let mut store = self.cookies.lock().unwrap();
+        if store.capture(headers) {
+            if let Err(e) = store.save() {
+                eprintln!("⚠️  Не удалось сохранить сессию Fragment: {}", e);
+            }
+        }
+    }
+
+    async fn get_page(&self, path: &str) -> std::result::Result<String, FragmentError> {
+        let response = self.client
+            .get(format!("https://{}{}", FRAGMENT_DOMAIN, path))
+            .header("User-Agent", USER_AGENT)
+            .header(header::COOKIE, self.cookie_header())
+            .send()
+            .await?;
+        self.capture_cookies(response.headers());
+
+        Ok(response.text().await?)
+    }
+
+    fn api_url(&self) -> String {
+        format!("https://{}/api?hash={}", FRAGMENT_DOMAIN, self.hash.read().unwrap())
+    }
+
+    // Загружает страницы Fragment и берет актуальный hash из конфига во встроенном скрипте
+    async fn refresh_hash(&self) -> std::result::Result<String, FragmentError> {
+        let mut last_page = String::new();
+
+        for page in FRAGMENT_HASH_PAGES {
+            let html = self.get_page(page).await?;
+
+            if let Some(hash) = parse_api_hash(&html) {
+                println!("🔑 Новый hash Fragment API: {}", hash);
+                *self.hash.write().unwrap() = hash.clone();
+                return Ok(hash);
+            }
+            last_page = html;
+        }
+
+        Err(FragmentError::InvalidHash {
+            message: "hash не найден на страницах Fragment".to_string(),
+            body: last_page,
+        })
+    }
+
+    // Единая точка вызова Fragment API: отправка, разбор JSON и классификация ошибок.
+    // При неверном hash он обновляется со страницы Fragment, при истекшей сессии клиент
+    // входит заново (если задан кошелек), и запрос повторяется один раз
+    async fn request<T: DeserializeOwned>(
+        &self,
+        params: &HashMap<&str, String>,
+        referer: Option<&str>,
+    ) -> Result<(T, String)> {
+        if self.hash.read().unwrap().is_empty() {
+            self.refresh_hash().await?;
+        }
+        if self.session_wallet.is_some() && self.cookies.lock().unwrap().session_expired() {
+            self.refresh_session().await?;
+        }
+
+        match self.request_once(params, referer).await {
+            Err(FragmentError::InvalidHash { .. }) => {
+                self.refresh_hash().await?;
+                Ok(self.request_once(params, referer).await?)
+            }
+            Err(e) if e.is_session_expired() && self.session_wallet.is_some() => {
+                self.refresh_session().await?;
+                Ok(self.request_once(params, referer).await?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    async fn request_once<T: DeserializeOwned>(
+        &self,
+        params: &HashMap<&str, String>,
+        referer: Option<&str>,
+    ) -> std::result::Result<(T, String), FragmentError> {
+        let response = self.send(params, referer).await?;
+        Self::parse_response(params, response).await
+    }
+
+    async fn refresh_session(&self) -> Result<()> {
+        if let Some(wallet) = &self.session_wallet {
+            println!("🔄 Сессия Fragment истекла, выполняется повторный вход...");
+            self.login(wallet.as_ref()).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        params: &HashMap<&str, String>,
+        referer: Option<&str>,
+    ) -> std::result::Result<reqwest::Response, FragmentError> {
+        let mut request = self.client
+            .post(self.api_url())
+            .header(header::ACCEPT, "application/json, text/javascript, */*; q=0.01")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded; charset=UTF-8")
+            .header("Origin", "https://fragment.com")
+            .header("User-Agent", USER_AGENT)
+            .header("X-Requested-With", "XMLHttpRequest")
+            .header(header::COOKIE, self.cookie_header());
+
+        if let Some(referer) = referer {
+            request = request.header("Referer", referer);
+        }
+
+        let response = request.form(params).send().await?;
+        self.capture_cookies(response.headers());
+
+        Ok(response)
+    }
+
+    async fn parse_response<T: DeserializeOwned>(
+        params: &HashMap<&str, String>,
+        response: reqwest::Response,
+    ) -> std::result::Result<(T, String), FragmentError> {
+        let status = response.status();
+        let body = response.text().await?;
+
+        let method = params.get("method").map(String::as_str).unwrap_or("?");
+        println!("{}: {}", method, body);
+
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(FragmentError::RateLimited { message: status.to_string(), body });
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(FragmentError::SessionExpired { message: status.to_string(), body });
+        }
+        // Вместо JSON Fragment отдает HTML-страницу, например, при сбросе сессии
+        if body.trim_start().starts_with('<') {
+            return Err(FragmentError::UnexpectedHtml { body });
+        }
+
+        let value: Value = serde_json::from_str(&body).map_err(|e| FragmentError::Api {
+            message: format!("Некорректный JSON: {}", e),
+            body: body.clone(),
+        })?;
+
+        if let Some(error) = value.get("error").and_then(Value::as_str) {
+            return Err(FragmentError::from_message(error, &body));
+        }
+
+        match serde_json::from_value(value) {
+            Ok(result) => Ok((result, body)),
+            Err(e) => Err(FragmentError::Api {
+                message: format!("Неожиданный формат ответа: {}", e),
+                body,
+            }),
+        }
+    }
+
+    // Вход по TON Connect: Fragment выдает payload, кошелек подписывает ton_proof,
+    // в ответ приходят cookies сессии, привязанной к этому кошельку
+    async fn login(&self, wallet: &dyn ConnectWallet) -> Result<()> {
+        self.cookies.lock().unwrap().retain(&["stel_dt"]);
+
+        let page = self.get_page("/").await?;
+
+        if let Some(hash) = parse_api_hash(&page) {
+            *self.hash.write().unwrap() = hash;
+        }
+
+        let payload = parse_ton_proof_payload(&page).ok_or_else(|| FragmentError::Api {
+            message: "На странице Fragment нет payload для ton_proof".to_string(),
+            body: page.clone(),
+        })?;
+
+        let address = wallet.address()?;
+        let state_init = wallet.state_init()?.to_boc()?;
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+        let signature = wallet.sign_ton_proof(FRAGMENT_DOMAIN, timestamp, &payload)?;
+
+        let account = json!({
+            "address": address.to_raw(),
+            "chain": wallet.network().ton_connect_chain(),
+            "walletStateInit": state_init,
+            "publicKey": hex::encode(wallet.public_key()),
+        });
+        let proof = json!({
+            "timestamp": timestamp,
+            "domain": {"lengthBytes": FRAGMENT_DOMAIN.len(), "value": FRAGMENT_DOMAIN},
+            "signature": general_purpose::STANDARD.encode(&signature),
+            "payload": payload,
+            "state_init": state_init,
+        });
+
+        let mut params = HashMap::new();
+        params.insert("account", account.to_string());
+        params.insert("device", self.device.to_json(wallet.max_messages()).to_string());
+        params.insert("proof", proof.to_string());
+        params.insert("method", "checkTonProofAuth".to_string());
+
+        let (_, body): (Value, String) = self.request_once(&params, Some("https://fragment.com/")).await?;
+
+        if self.cookies.lock().unwrap().session_expired() {
+            return Err(FragmentError::SessionExpired {
+                message: "Fragment не выдал cookies сессии".to_string(),
+                body,
+            }.into());
+        }
+
+        println!("✅ Вход в Fragment выполнен: {}", wallet.get_address()?);
+        Ok(())
+    }
+
+    async fn search_recipient(&self, params: &HashMap<&str, String>) -> Result<Recipient> {
+        let (result, body): (RecipientResponse, String) = self.request(params, None).await?;
+
+        Ok(result.found.ok_or(FragmentError::NotFound {
+            message: "Получатель не найден".to_string(),
+            body,
+        })?)
+    }
+
+    async fn init_request(&self, params: &HashMap<&str, String>) -> Result<String> {
+        let (result, body): (ReqIdResponse, String) = self.request(params, None).await?;
+
+        Ok(result.req_id.ok_or(FragmentError::Api {
+            message: "Не удалось создать запрос".to_string(),
+            body,
+        })?)
+    }
+
+    async fn fetch_recipient(&self, query: &TelegramUsername) -> Result<Recipient> {
+        let mut params = HashMap::new();
+        params.insert("query", query.as_str().to_string());
+        params.insert("method", "searchStarsRecipient".to_string());
+
+        self.search_recipient(&params).await
+    }
+
+    // Проверка пользователя без покупки: None, если Fragment его не нашел
+    async fn check_user(&self, username: &TelegramUsername) -> Result<Option<Recipient>> {
+        match self.fetch_recipient(username).await {
+            Ok(recipient) => Ok(Some(recipient)),
+            Err(Error::Fragment(FragmentError::NotFound { .. })) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn fetch_req_id(&self, recipient: &str, quantity: i32) -> Result<String> {
+        let mut params = HashMap::new();
+        params.insert("recipient", recipient.to_string());
+        params.insert("quantity", quantity.to_string());
+        params.insert("method", "initBuyStarsRequest".to_string());
+
+        self.init_request(&params).await
+    }
+
+    async fn fetch_buy_link(
+        &self,
+        wallet: &dyn ConnectWallet,
+        recipient: &str,
+        req_id: &str,
+        quantity: i32,
+        show_sender: bool,
+    ) -> Result<Vec<Message>> {
+        let referer = format!("https://fragment.com/stars/buy?recipient={}&quantity={}", recipient, quantity);
+        self.fetch_link(wallet, "getBuyStarsLink", &referer, req_id, show_sender).await
+    }
+
+    async fn fetch_premium_recipient(&self, query: &TelegramUsername, months: u8) -> Result<Recipient> {
+        let mut params = HashMap::new();
+        params.insert("query", query.as_str().to_string());
+        params.insert("months", months.to_string());
+        params.insert("method", "searchPremiumGiftRecipient".to_string());
+
+        self.search_recipient(&params).await
+    }
+
+    async fn fetch_premium_req_id(&self, recipient: &str, months: u8) -> Result<String> {
+        let mut params = HashMap::new();
+        params.insert("recipient", recipient.to_string());
+        params.insert("months", months.to_string());
+        params.insert("method", "initGiftPremiumRequest".to_string());
+
+        self.init_request(&params).await
+    }
+
+    async fn fetch_premium_link(
+        &self,
+        wallet: &dyn ConnectWallet,
+        recipient: &str,
+        req_id: &str,
+        months: u8,
+        show_sender: bool,
+    ) -> Result<Vec<Message>> {
+        let referer = format!("https://fragment.com/premium/gift?recipient={}&months={}", recipient, months);
+        self.fetch_link(wallet, "getGiftPremiumLink", &referer, req_id, show_sender).await
+    }
+
+    async fn fetch_ads_recipient(&self, query: &TelegramUsername) -> Result<Recipient> {
+        let mut params = HashMap::new();
+        params.insert("query", query.as_str().to_string());
+        params.insert("method", "searchAdsTopupRecipient".to_string());
+
+        self.search_recipient(&params).await
+    }
+
+    async fn fetch_ads_req_id(&self, recipient: &str, amount: u32) -> Result<String> {
+        let mut params = HashMap::new();
+        params.insert("recipient", recipient.to_string());
+        params.insert("amount", amount.to_string());
+        params.insert("method", "initAdsTopupRequest".to_string());
+
+        self.init_request(&params).await
+    }
+
+    async fn fetch_ads_link(&self, wallet: &dyn ConnectWallet, recipient: &str, req_id: &str, amount: u32) -> Result<Vec<Message>> {
+        let referer = format!("https://fragment.com/ads/topup?recipient={}&amount={}", recipient, amount);
+        self.fetch_link(wallet, "getAdsTopupLink", &referer, req_id, false).await
+    }
+
+    async fn fetch_giveaway_recipient(&self, query: &TelegramUsername) -> Result<Recipient> {
+        let mut params = HashMap::new();
+        params.insert("query", query.as_str().to_string());
+        params.insert("method", "searchStarsGiveawayRecipient".to_string());
+
+        self.search_recipient(&params).await
+    }
+
+    async fn fetch_giveaway_req_id(&self, recipient: &str, winners: u32, quantity: u32) -> Result<String> {
+        let mut params = HashMap::new();
+        params.insert("recipient", recipient.to_string());
+        params.insert("winners", winners.to_string());
+        params.insert("quantity", quantity.to_string());
+        params.insert("method", "initStarsGiveawayRequest".to_string());
+
+        self.init_request(&params).await
+    }
+
+    async fn fetch_giveaway_link(&self, wallet: &dyn ConnectWallet, recipient: &str, req_id: &str, winners: u32, quantity: u32) -> Result<Vec<Message>> {
+        let referer = format!(
+            "https://fragment.com/stars/giveaway?recipient={}&winners={}&quantity={}",
+            recipient, winners, quantity
+        );
+        self.fetch_link(wallet, "getStarsGiveawayLink", &referer, req_id, false).await
+    }
+
+    async fn quote_stars(&self, quantity: i32) -> Result<Quote> {
+        let mut params = HashMap::new();
+        params.insert("stars", String::new());
+        params.insert("quantity", quantity.to_string());
+        params.insert("method", "updateStarsPrices".to_string());
+
+        self.fetch_quote(&params).await
+    }
+
+    async fn quote_premium(&self, months: u8) -> Result<Quote> {
+        let mut params = HashMap::new();
+        params.insert("mode", "new".to_string());
+        params.insert("months", months.to_string());
+        params.insert("method", "updatePremiumState".to_string());
+
+        self.fetch_quote(&params).await
+    }
+
+    // Цена запрашивается без создания запроса на покупку
+    async fn fetch_quote(&self, params: &HashMap<&str, String>) -> Result<Quote> {
+        let (result, body): (PriceResponse, String) = self.request(params, None).await?;
+
+        if let Some(true) = result.ok {
+            if let Some(quote) = result.cur_price.as_deref().and_then(parse_quote) {
+                return Ok(quote);
+            }
+        }
+
+        Err(FragmentError::Api {
+            message: "Не удалось получить цену".to_string(),
+            body,
+        }.into())
+    }
+
+    async fn search_auctions(
+        &self,
+        kind: CollectibleKind,
+        query: &str,
+        filter: AuctionFilter,
+        offset: Option<&str>,
+    ) -> Result<AuctionPage> {
+        let mut params = HashMap::new();
+        params.insert("type", kind.search_type().to_string());
+        params.insert("query", query.to_string());
+        params.insert("filter", filter.as_param().to_string());
+        params.insert("offset_id", offset.unwrap_or_default().to_string());
+        params.insert("method", "searchAuctions".to_string());
+
+        let (result, body): (AuctionSearchResponse, String) = self.request(&params, None).await?;
+
+        match result.html {
+            Some(html) => Ok(AuctionPage {
+                lots: parse_auction_list(&html),
+                next_offset: result.next_offset_id.and_then(|v| match v {
+                    Value::String(s) if !s.is_empty() => Some(s),
+                    Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                }),
+            }),
+            None => Err(FragmentError::Api {
+                message: "Не удалось получить список аукционов".to_string(),
+                body,
+            }.into()),
+        }
+    }
+
+    async fn list_for_sale(&self, kind: CollectibleKind, offset: Option<&str>) -> Result<AuctionPage> {
+        self.search_auctions(kind, "", AuctionFilter::Sale, offset).await
+    }
+
+    async fn fetch_auction_item(&self, kind: CollectibleKind, slug: &str) -> Result<AuctionItem> {
+        let slug = kind.normalize_slug(slug)?;
+        let html = self.get_page(&kind.item_path(&slug)).await?;
+
+        parse_auction_item(&html, kind, &slug)
+            .ok_or_else(|| FragmentError::UnexpectedHtml { body: html }.into())
+    }
+
+    // Общий шаг для всех покупок: Fragment отдает готовую транзакцию для кошелька, который будет платить
+    async fn fetch_link(
+        &self,
+        wallet: &dyn ConnectWallet,
+        method: &str,
+        referer: &str,
+        req_id: &str,
+        show_sender: bool,
+    ) -> Result<Vec<Message>> {
+        let mut params = HashMap::new();
+        params.insert("id", req_id.to_string());
+        params.insert("show_sender", if show_sender { "1" } else { "0" }.to_string());
+        params.insert("method", method.to_string());
+
+        self.fetch_wallet_link(wallet, referer, params).await
+    }
+
+    // Ставка на аукционе; покупка по фиксированной цене — ставка, равная цене продажи
+    async fn fetch_bid_link(
+        &self,
+        wallet: &dyn ConnectWallet,
+        kind: CollectibleKind,
+        slug: &str,
+        bid_ton: f64,
+    ) -> Result<Vec<Message>> {
+        let referer = format!("https://{}{}", FRAGMENT_DOMAIN, kind.item_path(slug));
+
+        let mut params = HashMap::new();
+        params.insert("type", kind.search_type().to_string());
+        match kind {
+            CollectibleKind::Username => params.insert("username", slug.to_string()),
+            CollectibleKind::Number => params.insert("number", slug.to_string()),
+        };
+        params.insert("bid", bid_ton.to_string());
+        params.insert("method", "getBidLink".to_string());
+
+        self.fetch_wallet_link(wallet, &referer, params).await
+    }
+
+    // Дополняет запрос данными кошелька, который будет подписывать транзакцию.
+    // Счет может состоять из нескольких сообщений: оплачиваются все сразу
+    async fn fetch_wallet_link(
+        &self,
+        wallet: &dyn ConnectWallet,
+        referer: &str,
+        mut params: HashMap<&str, String>,
+    ) -> Result<Vec<Message>> {
+        params.insert("address", wallet.address()?.to_raw());
+        params.insert("chain", wallet.network().ton_connect_chain());
+        params.insert("walletStateInit", wallet.state_init()?.to_boc()?);
+        params.insert("publicKey", hex::encode(wallet.public_key()));
+        params.insert("features", DeviceProfile::features(wallet.max_messages()).to_string());
+        params.insert("maxProtocolVersion", "2".to_string());
+        params.insert("platform", self.device.platform.clone());
+        params.insert("appName", self.device.app_name.clone());
+        params.insert("appVersion", self.device.app_version.clone());
+        params.insert("transaction", "1".to_string());
+
+        let (result, body): (BuyLinkResponse, String) = self.request(&params, Some(referer)).await?;
+
+        if let Some(true) = result.ok {
+            if let Some(transaction) = result.transaction {
+                if !transaction.messages.is_empty() {
+                    return Ok(transaction.messages);
+                }
+            }
+        }
+
+        Err(FragmentError::Api {
+            message: "Не удалось получить данные транзакции".to_string(),
+            body,
+        }.into())
+    }
+}
+
+// СЕТЬ
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Network {
+    Mainnet,
+    Testnet,
+    // Своя сеть (например, локальная): global_id и адреса toncenter-совместимого API
+    Custom { global_id: i32, api_url: String, index_url: String },
+}
+
+impl Network {
+    fn global_id(&self) -> i32 {
+        match self {
+            Network::Mainnet => -239,
+            Network::Testnet => -3,
+            Network::Custom { global_id, .. } => *global_id,
+        }
+    }
+
+    // Флаг testnet в user-friendly адресах
+    fn is_testnet(&self) -> bool {
+        *self != Network::Mainnet
+    }
+
+    fn api_url(&self) -> &str {
+        match self {
+            Network::Mainnet => TON_API_URL,
+            Network::Testnet => TESTNET_API_URL,
+            Network::Custom { api_url, .. } => api_url,
+        }
+    }
+
+    fn index_url(&self) -> &str {
+        match self {
+            Network::Mainnet => TON_INDEX_URL,
+            Network::Testnet => TESTNET_INDEX_URL,
+            Network::Custom { index_url, .. } => index_url,
+        }
+    }
+
+    // Идентификатор сети в TON Connect: "-239" для mainnet, "-3" для testnet
+    fn ton_connect_chain(&self) -> String {
+        self.global_id().to_string()
+    }
+}
+
+// TON WALLET
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WalletVersion {
+    V4R2,
+    V5R1,
+}
+
+impl WalletVersion {
+    // Сколько внутренних сообщений кошелек отправляет за одну подпись
+    fn max_messages(self) -> usize {
+        match self {
+            WalletVersion::V4R2 => 4,
+            WalletVersion::V5R1 => 255,
+        }
+    }
+
+    fn wallet_id(self, workchain: i8, network: &Network) -> u32 {
+        match self {
+            WalletVersion::V4R2 => 698983191,
+            // network_global_id XOR контекст: 1, workchain:int8, version:8 = 0, subwallet:15 = 0
+            WalletVersion::V5R1 => (network.global_id() as u32) ^ ((1 << 31) | ((workchain as u8 as u32) << 23)),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    // Режим отправки внутреннего сообщения (send_raw_message)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct SendMode: u8 {
+        const PAY_FEES_SEPARATELY = 1;
+        const IGNORE_ERRORS = 2;
+        const BOUNCE_ON_ACTION_FAIL = 16;
+        const DESTROY_IF_ZERO = 32;
+        const CARRY_REMAINING_BALANCE = 64;
+        const CARRY_ALL_BALANCE = 128;
+    }
+}
+
+impl SendMode {
+    // Обычный перевод: комиссия сверху суммы, ошибки не блокируют кошелек (mode 3)
+    const DEFAULT: Self = Self::PAY_FEES_SEPARATELY.union(Self::IGNORE_ERRORS);
+
+    // Отклоняет только противоречивые флаги. DESTROY_IF_ZERO допустим и сам по себе,
+    // и с передачей остатка или всего баланса
+    fn validate(self) -> std::result::Result<(), ConfigError> {
+        if self.contains(Self::CARRY_REMAINING_BALANCE | Self::CARRY_ALL_BALANCE) {
+            return Err(ConfigError::InvalidSendMode {
+                mode: self.bits(),
+                reason: "нельзя одновременно передать остаток входящего сообщения и весь баланс",
+            });
+        }
+        Ok(())
+    }
+}
+
+// Внутреннее сообщение, которое кошелек отправит от своего имени
+#[derive(Debug, Clone)]
+struct OutgoingMessage {
+    destination: TonAddress,
+    amount: u64,
+    bounce: bool,
+    body: TonCell,
+    mode: SendMode,
+}
+
+impl OutgoingMessage {
+    // bounce берется из формата адреса: на "UQ..." деньги уходят без возврата
+    fn new(destination: &str, amount: u64, body: TonCell) -> Result<Self> {
+        Ok(Self {
+            destination: TonAddress::parse(destination)?,
+            amount,
+            bounce: TonAddress::is_bounceable(destination),
+            body,
+            mode: SendMode::DEFAULT,
+        })
+    }
+
+    fn with_mode(mut self, mode: SendMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // Сообщение из транзакции, которую выдал Fragment
+    fn from_link(link: &Message) -> Result<Self> {
+        let amount: u64 = link
+            .amount
+            .parse()
+            .map_err(|_| VerificationError::InvalidAmount(link.amount.clone()))?;
+        let body = if link.payload.is_empty() {
+            TonCell::new()
+        } else {
+            TonCell::from_boc(&link.payload)?
+        };
+
+        Self::new(&link.address, amount, body)
+    }
+
+    // int_msg_info$0 ihr_disabled bounce bounced src dest value ihr_fee fwd_fee created_lt created_at init body
+    fn to_cell(&self) -> TonCell {
+        let mut int_msg = TonCell::new();
+        int_msg.write_bit(false); // int_msg_info
+        int_msg.write_bit(true); // ihr_disabled
+        int_msg.write_bit(self.bounce);
+        int_msg.write_bit(false); // bounced
+        int_msg.write_address_none(); // src
+        int_msg.write_ton_address(&self.destination);
+        int_msg.write_coins(self.amount as u128);
+        int_msg.write_bit(false); // extra currencies
+        int_msg.write_coins(0); // ihr_fee
+        int_msg.write_coins(0); // fwd_fee
+        int_msg.write_uint(0, 64); // created_lt
+        int_msg.write_uint(0, 32); // created_at
+        int_msg.write_bit(false); // init
+        int_msg.write_bit(true); // body в отдельной ячейке
+        int_msg.add_ref(self.body.clone());
+        int_msg
+    }
+}
+
+// Кошелек, которым можно войти в Fragment по TON Connect и получить для него счет:
+// адрес, StateInit и публичный ключ уходят в запросы, ton_proof подписывается его ключом
+trait ConnectWallet: Send + Sync {
+    fn state_init(&self) -> Result<TonCell>;
+    fn workchain(&self) -> i8;
+    fn public_key(&self) -> &[u8];
+    fn private_key(&self) -> &[u8];
+    fn network(&self) -> &Network;
+    // Сколько сообщений кошелек отправляет одной подписью (features в TON Connect)
+    fn max_messages(&self) -> usize;
+
+    // Адрес кошелька — hash его StateInit
+    fn address(&self) -> Result<TonAddress> {
+        Ok(TonAddress {
+            workchain: self.workchain(),
+            hash: self.state_init()?.hash(),
+        })
+    }
+
+    fn get_address(&self) -> Result<String> {
+        Ok(self.address()?.to_friendly(false, self.network().is_testnet()))
+    }
+
+    // Подпись ton_proof по спецификации TON Connect
+    fn sign_ton_proof(&self, domain: &str, timestamp: u64, payload: &str) -> Result<Vec<u8>> {
+        let address = self.address()?;
+
+        let mut message = b"ton-proof-item-v2/".to_vec();
+        message.extend_from_slice(&(address.workchain as i32).to_be_bytes());
+        message.extend_from_slice(&address.hash);
+        message.extend_from_slice(&(domain.len() as u32).to_le_bytes());
+        message.extend_from_slice(domain.as_bytes());
+        message.extend_from_slice(&timestamp.to_le_bytes());
+        message.extend_from_slice(payload.as_bytes());
+
+        let mut full = vec![0xff, 0xff];
+        full.extend_from_slice(b"ton-connect");
+        full.extend_from_slice(&TonCrypto::sha256(&message));
+
+        TonCrypto::sign(&TonCrypto::sha256(&full), self.private_key())
+    }
+}
+
+struct TonWallet {
+    public_key: Vec<u8>,
+    private_key: Vec<u8>,
+    workchain: i8,
+    version: WalletVersion,
+    network: Network,
+    wallet_id: u32,
+}
+
+impl TonWallet {
+    fn new(mnemonic: &[String], workchain: i8) -> Result<Self> {
+        let (public_key, private_key) = TonCrypto::mnemonic_to_keys(mnemonic)?;
+        
+        Ok(Self {
+            public_key,
+            private_key,
+            workchain,
+            version: WalletVersion::V4R2,
+            network: Network::Mainnet,
+            wallet_id: WalletVersion::V4R2.wallet_id(workchain, &Network::Mainnet),
+        })
+    }
+
+    // Та же мнемоника в другой версии кошелька дает другой адрес
+    fn with_version(mut self, version: WalletVersion) -> Self {
+        self.version = version;
+        self.wallet_id = version.wallet_id(self.workchain, &self.network);
+        self
+    }
+
+    // У V5R1 id кошелька, а значит и адрес, зависит от сети
+    fn with_network(mut self, network: Network) -> Self {
+        self.wallet_id = self.version.wallet_id(self.workchain, &network);
+        self.network = network;
+        self
+    }
+
+    // Зашифрованный комментарий для владельца their_public_key. Солью служит наш адрес,
+    // поэтому расшифровать его сможем и мы, и получатель
+    fn encrypt_comment(&self, text: &str, their_public_key: &[u8; 32]) -> Result<TonCell> {
+        let salt = self.address()?.to_friendly(true, false);
+        let shared = TonCrypto::shared_key(&self.private_key, their_public_key)?;
+
+        let mut data: Vec<u8> = self
+            .public_key
+            .iter()
+            .zip(their_public_key)
+            .map(|(a, b)| a ^ b)
+            .collect();
+        data.extend(TonCrypto::encrypt_data(text.as_bytes(), &shared, salt.as_bytes()));
+
+        Ok(snake_cell(ENCRYPTED_COMMENT_OP, &data))
+    }
+
+    // Расшифровка комментария, отправленного с sender (с нашего кошелька или на него)
+    fn decrypt_comment(&self, cell: &TonCell, sender: &TonAddress) -> Result<String> {
+        let mut slice = CellSlice::new(cell);
+        if slice.read_uint(32)? != ENCRYPTED_COMMENT_OP as u64 {
+            return Err(CryptoError::Cipher.into());
+        }
+        let data = slice.read_snake_bytes()?;
+        if data.len() < 32 {
+            return Err(CryptoError::Cipher.into());
+        }
+
+        let mut their_public_key = [0u8; 32];
+        for (i, byte) in their_public_key.iter_mut().enumerate() {
+            *byte = data[i] ^ self.public_key[i];
+        }
+
+        let shared = TonCrypto::shared_key(&self.private_key, &their_public_key)?;
+        let salt = sender.to_friendly(true, false);
+        let plain = TonCrypto::decrypt_data(&data[32..], &shared, salt.as_bytes())?;
+
+        String::from_utf8(plain).map_err(|_| CryptoError::Cipher.into())
+    }
+
+    // Тело внешнего сообщения (без подписи) с пачкой внутренних сообщений
+    fn create_transfer_message(&self, messages: &[OutgoingMessage], seqno: u32, valid_until: u64) -> Result<TonCell> {
+        if messages.is_empty() || messages.len() > self.version.max_messages() {
+            return Err(ConfigError::InvalidParameter(format!(
+                "Кошелек {:?} отправляет от 1 до {} сообщений, передано {}",
+                self.version,
+                self.version.max_messages(),
+                messages.len()
+            )).into());
+        }
+        for message in messages {
+            message.mode.validate()?;
+        }
+        // После сообщения со всем балансом следующим отправлять уже нечего
+        if let Some(pos) = messages.iter().position(|m| m.mode.contains(SendMode::CARRY_ALL_BALANCE)) {
+            if pos != messages.len() - 1 {
+                return Err(ConfigError::InvalidSendMode {
+                    mode: messages[pos].mode.bits(),
+                    reason: "сообщение со всем балансом должно быть последним",
+                }.into());
+            }
+        }
+
+        let mut body = TonCell::new();
+        match self.version {
+            WalletVersion::V4R2 => {
+                body.write_uint(self.wallet_id as u64, 32);
+                body.write_uint(valid_until, 32);
+                body.write_uint(seqno as u64, 32);
+                body.write_uint(0, 8); // op: simple send
+                for message in messages {
+                    body.write_uint(message.mode.bits() as u64, 8);
+                    body.add_ref(message.to_cell());
+                }
+            }
+            WalletVersion::V5R1 => {
+                // out_list$_ prev:^(OutList n) action:OutAction, начиная с пустой ячейки
+                let mut out_list = TonCell::new();
+                for message in messages {
+                    let mut action = TonCell::new();
+                    action.add_ref(out_list);
+                    action.write_uint(0x0ec3c86d, 32); // action_send_msg
+                    action.write_uint(message.mode.bits() as u64, 8);
+                    action.add_ref(message.to_cell());
+                    out_list = action;
+                }
+
+                body.write_uint(0x7369676e, 32); // signed_external
+                body.write_uint(self.wallet_id as u64, 32);
+                body.write_uint(valid_until, 32);
+                body.write_uint(seqno as u64, 32);
+                body.write_bit(true); // out_list
+                body.add_ref(out_list);
+                body.write_bit(false); // extended actions
+            }
+        }
+
+        Ok(body)
+    }
+
+    // Подписывает тело и упаковывает его во внешнее сообщение к кошельку.
+    // Для первой транзакции (seqno 0) прикладывается StateInit, чтобы задеплоить кошелек
+    fn sign_external_message(&self, body: &TonCell, seqno: u32) -> Result<TonCell> {
+        let signature = TonCrypto::sign(&body.hash(), &self.private_key)?;
+
+        // V4 ждет подпись в начале тела, V5 — в конце
+        let mut signed_body = TonCell::new();
+        match self.version {
+            WalletVersion::V4R2 => {
+                signed_body.write_bytes(&signature);
+                signed_body.append(body);
+            }
+            WalletVersion::V5R1 => {
+                signed_body.append(body);
+                signed_body.write_bytes(&signature);
+            }
+        }
+
+        let mut ext_msg = TonCell::new();
+        ext_msg.write_uint(0b10, 2); // ext_in_msg_info
+        ext_msg.write_address_none(); // src
+        ext_msg.write_ton_address(&self.address()?);
+        ext_msg.write_coins(0); // import_fee
+        if seqno == 0 {
+            ext_msg.write_bit(true); // init
+            ext_msg.write_bit(true); // StateInit в отдельной ячейке
+            ext_msg.add_ref(self.state_init()?);
+        } else {
+            ext_msg.write_bit(false);
+        }
+        ext_msg.write_bit(true); // body в отдельной ячейке
+        ext_msg.add_ref(signed_body);
+
+        Ok(ext_msg)
+    }
+}
+
+impl ConnectWallet for TonWallet {
+    // StateInit кошелька: код контракта и начальные данные
+    fn state_init(&self) -> Result<TonCell> {
+        let mut data = TonCell::new();
+        let code = match self.version {
+            WalletVersion::V4R2 => {
+                data.write_uint(0, 32); // seqno
+                data.write_uint(self.wallet_id as u64, 32);
+                data.write_bytes(&self.public_key);
+                data.write_bit(false); // plugins
+                TonCell::from_boc(WALLET_V4R2_CODE)?
+            }
+            WalletVersion::V5R1 => {
+                data.write_bit(true); // is_signature_allowed
+                data.write_uint(0, 32); // seqno
+                data.write_uint(self.wallet_id as u64, 32);
+                data.write_bytes(&self.public_key);
+                data.write_bit(false); // extensions
+                TonCell::from_boc(WALLET_V5R1_CODE)?
+            }
+        };
+
+        let mut state_init = TonCell::new();
+        state_init.write_uint(0b00110, 5); // split_depth, special, code, data, library
+        state_init.add_ref(code);
+        state_init.add_ref(data);
+
+        Ok(state_init)
+    }
+
+    fn workchain(&self) -> i8 {
+        self.workchain
+    }
+
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn private_key(&self) -> &[u8] {
+        &self.private_key
+    }
+
+    fn network(&self) -> &Network {
+        &self.network
+    }
+
+    fn max_messages(&self) -> usize {
+        self.version.max_messages()
+    }
+}
+
+// HIGHLOAD WALLET V3
+// query_id: shift (13 бит) и bit_number (10 бит). Вместо seqno контракт помнит обработанные id,
+// поэтому сообщения с разными id можно отправлять параллельно
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct HighloadQueryId(u32);
+
+impl HighloadQueryId {
+    const MAX_SHIFT: u32 = 8191;
+    // bit_number 1023 не используется, как и в SDK
+    const MAX_BIT_NUMBER: u32 = 1022;
+    // Последний id SDK держит в резерве для аварийных сообщений и не выдает
+    const RESERVED: HighloadQueryId = HighloadQueryId((Self::MAX_SHIFT << 10) | Self::MAX_BIT_NUMBER);
+    const COUNT: u32 = (Self::MAX_SHIFT + 1) * (Self::MAX_BIT_NUMBER + 1) - 1;
+
+    fn shift(self) -> u32 {
+        self.0 >> 10
+    }
+
+    fn bit_number(self) -> u32 {
+        self.0 & 0x3ff
+    }
+
+    fn is_valid(self) -> bool {
+        self.bit_number() <= Self::MAX_BIT_NUMBER && self.0 < Self::RESERVED.0
+    }
+
+    // Следующий id по кругу: после bit_number 1022 — следующий shift, перед резервным — снова 0
+    fn next(self) -> Self {
+        let next = if self.bit_number() >= Self::MAX_BIT_NUMBER {
+            (self.shift() + 1) << 10
+        } else {
+            self.0 + 1
+        };
+        if next >= Self::RESERVED.0 {
+            HighloadQueryId(0)
+        } else {
+            HighloadQueryId(next)
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueryIdState {
+    next: u32,
+    // Выданные id и время выдачи в порядке выдачи
+    issued: std::collections::VecDeque<(HighloadQueryId, i64)>,
+}
+
+lazy_static::lazy_static! {
+    // Один QueryIdAllocator на файл в пределах процесса: каждая покупка создает свой
+    // TonTransaction, но id должны выдаваться из общего счетчика
+    static ref QUERY_ID_ALLOCATORS: Mutex<HashMap<String, Arc<QueryIdAllocator>>> = Mutex::new(HashMap::new());
+}
+
+// Выдает query_id по кругу. Id можно выдать повторно только через 2 × timeout: до этого
+// контракт еще хранит его среди обработанных, а сообщение с ним может быть в пути.
+// Состояние сохраняется в файл, чтобы перезапуск сервиса не начал выдачу заново с нуля.
+// Файл не должен использоваться несколькими процессами одновременно
+struct QueryIdAllocator {
+    state: Mutex<QueryIdState>,
+    path: Option<PathBuf>,
+    timeout: u32,
+}
+
+impl QueryIdAllocator {
+    fn new(timeout: u32) -> Self {
+        Self {
+            state: Mutex::new(QueryIdState::default()),
+            path: None,
+            timeout,
+        }
+    }
+
+    // Общий для процесса экземпляр для файла path: параллельные покупки не получат один id
+    fn shared(path: &str, timeout: u32) -> Result<Arc<Self>> {
+        let mut allocators = QUERY_ID_ALLOCATORS.lock().unwrap();
+        if let Some(allocator) = allocators.get(path) {
+            return Ok(Arc::clone(allocator));
+        }
+
+        let allocator = Arc::new(Self::open(path, timeout)?);
+        allocators.insert(path.to_string(), Arc::clone(&allocator));
+        Ok(allocator)
+    }
+
+    fn open(path: &str, timeout: u32) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let state = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| ConfigError::InvalidParameter(format!("Поврежден файл query_id: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => QueryIdState::default(),
+            Err(e) => return Err(ConfigError::from(e).into()),
+        };
+
+        Ok(Self {
+            state: Mutex::new(state),
+            path: Some(path),
+            timeout,
+        })
+    }
+
+    fn allocate(&self, now: i64) -> Result<HighloadQueryId> {
+        let mut state = self.state.lock().unwrap();
+
+        let reuse_after = 2 * self.timeout as i64;
+        while state.issued.front().map_or(false, |(_, issued_at)| now - issued_at > reuse_after) {
+            state.issued.pop_front();
+        }
+
+        // Выданные id идут подряд до next, поэтому next свободен, пока очередь не заполнена
+        if state.issued.len() as u32 >= HighloadQueryId::COUNT {
+            return Err(ConfigError::InvalidParameter("Закончились свободные query_id, нужно подождать".to_string()).into());
+        }
+
+        // Файл мог остаться от версии, которая выдавала bit_number 1023
+        let query_id = Some(HighloadQueryId(state.next))
+            .filter(|id| id.is_valid())
+            .unwrap_or(HighloadQueryId(0));
+        state.next = query_id.next().0;
+        state.issued.push_back((query_id, now));
+
+        if let Some(path) = &self.path {
+            let bytes = serde_json::to_vec(&*state).map_err(|e| ConfigError::Io(e.into()))?;
+            std::fs::write(path, bytes).map_err(ConfigError::from)?;
+        }
+
+        Ok(query_id)
+    }
+}
+
+// query_id выдает QueryIdAllocator в TonTransaction, сам кошелек только собирает и подписывает сообщения
+struct HighloadWallet {
+    public_key: Vec<u8>,
+    private_key: Vec<u8>,
+    workchain: i8,
+    network: Network,
+    subwallet_id: u32,
+    timeout: u32,
+}
+
+impl HighloadWallet {
+    fn new(mnemonic: &[String], workchain: i8) -> Result<Self> {
+        let (public_key, private_key) = TonCrypto::mnemonic_to_keys(mnemonic)?;
+
+        Ok(Self {
+            public_key,
+            private_key,
+            workchain,
+            network: Network::Mainnet,
+            subwallet_id: HIGHLOAD_SUBWALLET_ID,
+            timeout: HIGHLOAD_TIMEOUT,
+        })
+    }
+
+    // Адрес highload-кошелька от сети не зависит, сеть нужна для API и TON Connect
+    fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    // Внешнее сообщение с одним внутренним. Контракт примет его, пока
+    // created_at > now - timeout, и только один раз для каждого query_id
+    fn create_external_message(
+        &self,
+        message: &OutgoingMessage,
+        query_id: HighloadQueryId,
+        created_at: u64,
+        deploy: bool,
+    ) -> Result<TonCell> {
+        message.mode.validate()?;
+
+        let mut inner = TonCell::new();
+        inner.write_uint(self.subwallet_id as u64, 32);
+        inner.add_ref(message.to_cell());
+        inner.write_uint(message.mode.bits() as u64, 8);
+        inner.write_uint(query_id.shift() as u64, 13);
+        inner.write_uint(query_id.bit_number() as u64, 10);
+        inner.write_uint(created_at, 64);
+        inner.write_uint(self.timeout as u64, 22);
+
+        let signature = TonCrypto::sign(&inner.hash(), &self.private_key)?;
+        let mut body = TonCell::new();
+        body.add_ref(inner);
+        body.write_bytes(&signature);
+
+        let mut ext_msg = TonCell::new();
+        ext_msg.write_uint(0b10, 2); // ext_in_msg_info
+        ext_msg.write_address_none(); // src
+        ext_msg.write_ton_address(&self.address()?);
+        ext_msg.write_coins(0); // import_fee
+        if deploy {
+            ext_msg.write_bit(true); // init
+            ext_msg.write_bit(true); // StateInit в отдельной ячейке
+            ext_msg.add_ref(self.state_init()?);
+        } else {
+            ext_msg.write_bit(false);
+        }
+        ext_msg.write_bit(true); // body в отдельной ячейке
+        ext_msg.add_ref(body);
+
+        Ok(ext_msg)
+    }
+}
+
+impl ConnectWallet for HighloadWallet {
+    fn state_init(&self) -> Result<TonCell> {
+        let code = TonCell::from_boc(HIGHLOAD_V3_CODE)?;
+
+        let mut data = TonCell::new();
+        data.write_bytes(&self.public_key);
+        data.write_uint(self.subwallet_id as u64, 32);
+        data.write_bit(false); // old_queries
+        data.write_bit(false); // queries
+        data.write_uint(0, 64); // last_clean_time
+        data.write_uint(self.timeout as u64, 22);
+
+        let mut state_init = TonCell::new();
+        state_init.write_uint(0b00110, 5); // split_depth, special, code, data, library
+        state_init.add_ref(code);
+        state_init.add_ref(data);
+
+        Ok(state_init)
+    }
+
+    fn workchain(&self) -> i8 {
+        self.workchain
+    }
+
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn private_key(&self) -> &[u8] {
+        &self.private_key
+    }
+
+    fn network(&self) -> &Network {
+        &self.network
+    }
+
+    // Одно внутреннее сообщение на внешнее
+    fn max_messages(&self) -> usize {
+        1
+    }
+}
+
+// TON NFT
+// Содержимое NFT по TEP-64
+#[derive(Debug, Clone, PartialEq)]
+enum NftContent {
+    // 0x01 + snake-строка со ссылкой на JSON с метаданными
+    Offchain(String),
+    // 0x00 + словарь с метаданными в блокчейне (не разбирается)
+    Onchain(TonCell),
+    Unknown(TonCell),
+}
+
+impl NftContent {
+    fn parse(cell: &TonCell) -> Self {
+        let mut slice = CellSlice::new(cell);
+
+        match slice.read_uint(8) {
+            Ok(0x01) => match slice.read_snake_bytes() {
+                Ok(bytes) => NftContent::Offchain(String::from_utf8_lossy(&bytes).to_string()),
+                Err(_) => NftContent::Unknown(cell.clone()),
+            },
+            Ok(0x00) => NftContent::Onchain(cell.clone()),
+            _ => NftContent::Unknown(cell.clone()),
+        }
+    }
+}
+
+// Данные NFT из get_nft_data
+#[derive(Debug, Clone)]
+struct NftItem {
+    address: TonAddress,
+    initialized: bool,
+    // Индекс в коллекции, uint256 в hex
+    index: String,
+    collection: Option<TonAddress>,
+    owner: Option<TonAddress>,
+    content: NftContent,
+}
+
+impl NftItem {
+    fn kind(&self) -> Option<CollectibleKind> {
+        CollectibleKind::from_collection(self.collection.as_ref()?)
+    }
+
+    // Название для коллекций Fragment: "https://nft.fragment.com/username/durov.json" -> "@durov"
+    fn name(&self) -> Option<String> {
+        let NftContent::Offchain(url) = &self.content else {
+            return None;
+        };
+        let slug = url.rsplit('/').next()?.trim_end_matches(".json");
+
+        match self.kind()? {
+            CollectibleKind::Username => Some(format!("@{}", slug)),
+            CollectibleKind::Number => Some(format!("+{}", slug)),
+        }
+    }
+}
+
+// transfer#5fcc3d14 query_id:uint64 new_owner:MsgAddress response_destination:MsgAddress
+// custom_payload:(Maybe ^Cell) forward_amount:(VarUInteger 16) forward_payload:(Either Cell ^Cell)
+fn nft_transfer_body(
+    query_id: u64,
+    new_owner: &TonAddress,
+    response_destination: Option<&TonAddress>,
+    forward_amount: u64,
+    forward_payload: Option<&TonCell>,
+) -> TonCell {
+    let mut body = TonCell::new();
+    body.write_uint(0x5fcc3d14, 32);
+    body.write_uint(query_id, 64);
+    body.write_ton_address(new_owner);
+    match response_destination {
+        Some(address) => body.write_ton_address(address),
+        None => body.write_address_none(),
+    }
+    body.write_bit(false); // custom_payload
+    body.write_coins(forward_amount as u128);
+    match forward_payload {
+        Some(payload) => {
+            body.write_bit(true);
+            body.add_ref(payload.clone());
+        }
+        None => body.write_bit(false),
+    }
+    body
+}
+
+// Данные коллекции из get_collection_data
+#[derive(Debug, Clone)]
+struct NftCollection {
+    address: TonAddress,
+    // -1 у коллекций без последовательной нумерации (как у Fragment)
+    next_item_index: i64,
+    content: NftContent,
+    owner: Option<TonAddress>,
+}
+
+// NFT кошелька из одной коллекции вместе с ее данными
+#[derive(Debug, Clone)]
+struct OwnedCollection {
+    collection: NftCollection,
+    items: Vec<NftItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NftIndexResponse {
+    nft_items: Vec<NftIndexItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NftIndexItem {
+    address: String,
+}
+
+// Число из стека toncenter: ["num", "-0x1"]
+fn stack_num(entry: Option<&Value>) -> Result<String> {
+    entry
+        .and_then(|e| e.get(1))
+        .and_then(Value::as_str)
+        .map(|s| s.to_string())
+        .ok_or_else(|| ChainError::InvalidStack(format!("ожидалось число, получено {:?}", entry)).into())
+}
+
+fn stack_int(entry: Option<&Value>) -> Result<i64> {
+    let value = stack_num(entry)?;
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.as_str()),
+    };
+
+    let number = i64::from_str_radix(digits.trim_start_matches("0x"), 16)
+        .map_err(|_| ChainError::InvalidStack(format!("некорректное число: {}", value)))?;
+    Ok(if negative { -number } else { number })
+}
+
+fn stack_address(entry: Option<&Value>) -> Result<Option<TonAddress>> {
+    let cell = stack_cell(entry)?;
+    Ok(CellSlice::new(&cell).read_address()?)
+}
+
+// TON COMMENT
+// Текстовый комментарий: op 0 и строка UTF-8
+fn comment_cell(text: &str) -> TonCell {
+    snake_cell(0, text.as_bytes())
+}
+
+// op и данные snake-цепочкой: в первую ячейку после op помещается 123 байта, в каждую следующую — 127
+fn snake_cell(op: u32, bytes: &[u8]) -> TonCell {
+    let head_len = bytes.len().min(123);
+
+    // Цепочка собирается с конца: каждая ячейка ссылается на следующую
+    let mut tail: Option<TonCell> = None;
+    for chunk in bytes[head_len..].chunks(127).rev() {
+        let mut cell = TonCell::new();
+        cell.write_bytes(chunk);
+        if let Some(next) = tail.take() {
+            cell.add_ref(next);
+        }
+        tail = Some(cell);
+    }
+
+    let mut cell = TonCell::new();
+    cell.write_uint(op as u64, 32);
+    cell.write_bytes(&bytes[..head_len]);
+    if let Some(next) = tail {
+        cell.add_ref(next);
+    }
+    cell
+}
+
+// Обратное преобразование. None — если в ячейке не текстовый комментарий
+fn parse_comment(cell: &TonCell) -> Option<String> {
+    let mut slice = CellSlice::new(cell);
+    if slice.read_uint(32).ok()? != 0 {
+        return None;
+    }
+
+    String::from_utf8(slice.read_snake_bytes().ok()?).ok()
+}
+
+// Op зашифрованного комментария: pub_xor (32 байта), msg_key (16 байт), шифротекст
+const ENCRYPTED_COMMENT_OP: u32 = 0x2167da4b;
+
+// TON JETTON
+// transfer#0f8a7ea5 query_id:uint64 amount:(VarUInteger 16) destination:MsgAddress
+// response_destination:MsgAddress custom_payload:(Maybe ^Cell) forward_ton_amount:(VarUInteger 16)
+// forward_payload:(Either Cell ^Cell)
+fn jetton_transfer_body(
+    query_id: u64,
+    amount: u128,
+    destination: &TonAddress,
+    response_destination: Option<&TonAddress>,
+    forward_ton_amount: u64,
+    forward_payload: Option<&TonCell>,
+) -> TonCell {
+    let mut body = TonCell::new();
+    body.write_uint(0x0f8a7ea5, 32);
+    body.write_uint(query_id, 64);
+    body.write_coins(amount);
+    body.write_ton_address(destination);
+    match response_destination {
+        Some(address) => body.write_ton_address(address),
+        None => body.write_address_none(),
+    }
+    body.write_bit(false); // custom_payload
+    body.write_coins(forward_ton_amount as u128);
+    match forward_payload {
+        Some(payload) => {
+            body.write_bit(true);
+            body.add_ref(payload.clone());
+        }
+        None => body.write_bit(false),
+    }
+    body
+}
+
+// Данные jetton-кошелька из get_wallet_data
+#[derive(Debug, Clone)]
+struct JettonWallet {
+    address: TonAddress,
+    balance: u128,
+    owner: Option<TonAddress>,
+    master: Option<TonAddress>,
+}
+
+// Большое неотрицательное число из стека toncenter: ["num", "0x2540be400"]
+fn stack_u128(entry: Option<&Value>) -> Result<u128> {
+    let value = stack_num(entry)?;
+    u128::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| ChainError::InvalidStack(format!("некорректное число: {}", value)).into())
+}
+
+// Опрос до подтверждения сообщения id. Если valid_until прошел, а сообщение не подтверждено,
+// оно уже никогда не будет принято: его можно собрать заново без риска двойной оплаты.
+// Если же состояние выяснить не удалось, результат неизвестен и повторять отправку нельзя
+async fn wait_for_confirmation<F, Fut>(id: &str, valid_until: u64, mut confirmed: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<bool>>,
+{
+    loop {
+        tokio::time::sleep(Duration::from_secs(CONFIRMATION_POLL_SECS)).await;
+
+        let checked_at = chrono::Utc::now().timestamp() as u64;
+        // Запас на отставание времени блокчейна от нашего
+        let expired = checked_at > valid_until + CONFIRMATION_GRACE_SECS;
+        match confirmed().await {
+            Ok(true) => return Ok(()),
+            Ok(false) if expired => {
+                return Err(ChainError::Expired { id: id.to_string(), valid_until }.into());
+            }
+            Ok(false) => {}
+            // Временные сбои API не означают, что перевод не прошел, но ждать бесконечно нельзя
+            Err(e) if e.is_retryable() && !expired => {}
+            Err(e) => {
+                return Err(ChainError::Unconfirmed { id: id.to_string(), reason: e.to_string() }.into());
+            }
+        }
+    }
+}
+
+// TON TRANSACTION
+struct TonTransaction {
+    wallet: TonWallet,
+    client: Client,
+    // Сколько секунд подписанное сообщение остается действительным (valid_until)
+    ttl: u32,
+    // Highload-кошелек и выдача его query_id: если задан, счета Fragment оплачиваются с него
+    highload: Option<(HighloadWallet, Arc<QueryIdAllocator>)>,
+}
+
+impl TonTransaction {
+    fn new(mnemonic: &[&str]) -> Result<Self> {
+        let mnemonic_vec: Vec<String> = mnemonic.iter().map(|s| s.to_string()).collect();
+        let wallet = TonWallet::new(&mnemonic_vec, 0)?.with_network(NETWORK).with_version(WALLET_VERSION);
+        
+        let ton = Self {
+            wallet,
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+            ttl: MESSAGE_TTL,
+            highload: None,
+        };
+
+        if USE_HIGHLOAD_WALLET {
+            let highload = HighloadWallet::new(&mnemonic_vec, 0)?.with_network(NETWORK);
+            let query_ids = QueryIdAllocator::shared(HIGHLOAD_QUERY_IDS_PATH, HIGHLOAD_TIMEOUT)?;
+            return Ok(ton.with_highload(highload, query_ids));
+        }
+        Ok(ton)
+    }
+
+    fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn with_highload(mut self, wallet: HighloadWallet, query_ids: Arc<QueryIdAllocator>) -> Self {
+        self.highload = Some((wallet, query_ids));
+        self
+    }
+
+    // Кошелек, который платит Fragment: его адрес и StateInit уходят в запрос счета
+    fn payer(&self) -> &dyn ConnectWallet {
+        match &self.highload {
+            Some((wallet, _)) => wallet,
+            None => &self.wallet,
+        }
+    }
+
+    fn decode_payload(&self, payload_base64: &str, marker: &str) -> String {
+        if let Some(comment) = TonCell::from_boc(payload_base64).ok().as_ref().and_then(parse_comment) {
+            return comment;
+        }
+
+        let fixed = fix_base64_padding(payload_base64);
+        
+        match general_purpose::STANDARD.decode(&fixed) {
+            Ok(decoded) => {
+                let decoded_text: String = decoded
+                    .iter()
+                    .map(|&b| if b >= 32 && b < 127 { b as char } else { ' ' })
+                    .collect();
+
+                let clean_text = Regex::new(r"\s+")
+                    .unwrap()
+                    .replace_all(&decoded_text, " ")
+                    .trim()
+                    .to_string();
+
+                let pattern = format!(r"{}.*", regex::escape(marker));
+                if let Ok(re) = Regex::new(&pattern) {
+                    if let Some(mat) = re.find(&clean_text) {
+                        return mat.as_str().to_string();
+                    }
+                }
+
+                clean_text
+            }
+            Err(_) => payload_base64.to_string(),
+        }
+    }
+
+    // Fragment выставляет счет с комментарием о покупке; без него платить нельзя.
+    // Режим show_sender в комментарии не отражается (там только товар и Ref#), поэтому
+    // проверить его по счету нельзя: он сохраняется в журнале как запрошенный
+    fn verify_payload(&self, payload_base64: &str, marker: &str) -> Result<String> {
+        let comment = self.decode_payload(payload_base64, marker);
+
+        // Маркер ищется целым токеном: "1100 Telegram Stars" не подходит к заказу на 100
+        let pattern = format!(r"(?:^|[^\w]){}(?:[^\w]|$)", regex::escape(marker));
+        let matches = Regex::new(&pattern).is_ok_and(|re| re.is_match(&comment));
+        if !matches {
+            return Err(VerificationError::PayloadMismatch {
+                expected: marker.to_string(),
+                actual: comment,
+            }.into());
+        }
+
+        Ok(comment)
+    }
+
+    // Все сообщения счета уходят одной подписью, чтобы Fragment не получил оплату частично
+    async fn send_transaction(&self, messages: &[Message], marker: &str) -> Result<String> {
+        println!("\n🔐 Инициализация кошелька...");
+
+        let wallet_address = self.payer().get_address()?;
+        println!("✅ Адрес кошелька: {}", wallet_address);
+
+        println!("\n💸 Отправка транзакции...");
+        let mut outgoing = Vec::with_capacity(messages.len());
+        for message in messages {
+            let payload_decoded = self.decode_payload(&message.payload, marker);
+            let out = OutgoingMessage::from_link(message)?;
+
+            println!("   Получатель: {}", message.address);
+            println!("   Сумма: {:.4} TON ({} nanoTON)", out.amount as f64 / 1e9, out.amount);
+            println!("   Комментарий: {}", payload_decoded);
+            outgoing.push(out);
+        }
+
+        if self.highload.is_some() {
+            return self.send_highload(outgoing).await;
+        }
+        self.send_batch(outgoing).await
+    }
+
+    // Простой перевод TON с необязательным комментарием (выплаты, возвраты)
+    async fn send_ton(&self, dest: &str, amount_nano: u64, comment: Option<&str>) -> Result<String> {
+        println!("\n💸 Перевод TON...");
+        println!("   Получатель: {}", dest);
+        println!("   Сумма: {:.4} TON ({} nanoTON)", amount_nano as f64 / 1e9, amount_nano);
+        if let Some(comment) = comment {
+            println!("   Комментарий: {}", comment);
+        }
+
+        let body = comment.map(comment_cell).unwrap_or_else(TonCell::new);
+        self.send_message(dest, amount_nano, &body).await
+    }
+
+    // Перевод TON с комментарием, который может прочитать только получатель
+    async fn send_ton_encrypted(&self, dest: &str, amount_nano: u64, comment: &str) -> Result<String> {
+        let their_public_key = self.get_public_key(&TonAddress::parse(dest)?).await?;
+        let body = self.wallet.encrypt_comment(comment, &their_public_key)?;
+
+        println!("\n🔒 Перевод TON с зашифрованным комментарием...");
+        println!("   Получатель: {}", dest);
+        println!("   Сумма: {:.4} TON ({} nanoTON)", amount_nano as f64 / 1e9, amount_nano);
+
+        self.send_message(dest, amount_nano, &body).await
+    }
+
+    // Отправка с highload-кошелька: без seqno, поэтому вызовы можно запускать параллельно.
+    // Подтверждение — транзакция с нашим сообщением; после created_at + timeout сообщение истекает
+    async fn send_highload(&self, messages: Vec<OutgoingMessage>) -> Result<String> {
+        let (wallet, query_ids) = self
+            .highload
+            .as_ref()
+            .ok_or_else(|| ConfigError::InvalidParameter("Highload-кошелек не подключен".to_string()))?;
+        let message = match <[OutgoingMessage; 1]>::try_from(messages) {
+            Ok([message]) => message,
+            Err(messages) => {
+                return Err(ConfigError::InvalidParameter(format!(
+                    "Highload-кошелек отправляет 1 сообщение, передано {}",
+                    messages.len()
+                )).into());
+            }
+        };
+
+        let address = wallet.address()?;
+        let deploy = self.get_address_state(&address).await? != "active";
+        let now = chrono::Utc::now().timestamp();
+        let query_id = query_ids.allocate(now)?;
+        // created_at чуть в прошлом: время lite-сервера может отставать от нашего
+        let created_at = (now - 30) as u64;
+        let valid_until = created_at + wallet.timeout as u64;
+        println!("   Query id: {}", query_id.0);
+
+        let ext_msg = wallet.create_external_message(&message, query_id, created_at, deploy)?;
+        let tx_hash = hex::encode(ext_msg.hash());
+        self.submit_boc(&ext_msg.to_boc()?).await?;
+        println!("\n📤 Highload: {} nanoTON -> {}, ждем подтверждения...", message.amount, message.destination.to_friendly(message.bounce, wallet.network.is_testnet()));
+
+        // processed? говорит только, что id занят; нашим сообщение считается, если в транзакциях
+        // кошелька есть его hash. Иначе id занял кто-то другой и наше сообщение не будет принято
+        let msg_hash = ext_msg.hash();
+        let since = created_at.saturating_sub(CONFIRMATION_GRACE_SECS);
+        wait_for_confirmation(&format!("query_id {}", query_id.0), valid_until, || async {
+            if !self.is_highload_processed(&address, query_id).await? {
+                return Ok(false);
+            }
+            Ok(self.find_transaction(&address, &msg_hash, since).await?.is_some())
+        })
+        .await?;
+
+        println!("\n✅ Транзакция подтверждена!");
+        println!("📝 Hash: {}", tx_hash);
+
+        Ok(tx_hash)
+    }
+
+    // Get-метод processed?(query_id, need_clean) highload-кошелька: -1, если id уже обработан
+    async fn is_highload_processed(&self, address: &TonAddress, query_id: HighloadQueryId) -> Result<bool> {
+        // До развертывания get-методов нет, а значит и обработанных сообщений
+        if self.get_address_state(address).await? != "active" {
+            return Ok(false);
+        }
+
+        let stack = self
+            .run_get_method(
+                &address.to_raw(),
+                "processed?",
+                vec![json!(["num", query_id.0.to_string()]), json!(["num", "0"])],
+            )
+            .await?;
+        Ok(stack_int(stack.first())? != 0)
+    }
+
+    // Переводит весь баланс кошелька на dest (mode 128)
+    async fn sweep(&self, dest: &str) -> Result<String> {
+        println!("\n🧹 Перевод всего баланса на {}", dest);
+
+        let message = OutgoingMessage::new(dest, 0, TonCell::new())?.with_mode(SendMode::CARRY_ALL_BALANCE);
+        self.send_batch(vec![message]).await
+    }
+
+    // Подписывает и отправляет одно внутреннее сообщение с произвольным телом
+    async fn send_message(&self, recipient_address: &str, amount_nano: u64, body: &TonCell) -> Result<String> {
+        self.send_batch(vec![OutgoingMessage::new(recipient_address, amount_nano, body.clone())?]).await
+    }
+
+    // Несколько сообщений под одним seqno и одной подписью
+    async fn send_batch(&self, messages: Vec<OutgoingMessage>) -> Result<String> {
+        let seqno = self.get_seqno().await?;
+        let valid_until = chrono::Utc::now().timestamp() as u64 + self.ttl as u64;
+        println!("   Сообщений: {}", messages.len());
+        println!("   Seqno: {}", seqno);
+
+        // Создаем сообщение
+        let message = self.wallet.create_transfer_message(&messages, seqno, valid_until)?;
+
+        // Подписываем
+        let ext_msg = self.wallet.sign_external_message(&message, seqno)?;
+
+        // Отправляем через API. Hash считается заранее: он нужен, даже если ответ не дошел
+        let tx_hash = hex::encode(ext_msg.hash());
+        self.submit_boc(&ext_msg.to_boc()?).await?;
+        println!("\n📤 Сообщение отправлено, ждем подтверждения...");
+
+        self.wait_for_seqno(seqno, valid_until, &ext_msg.hash()).await?;
+
+        println!("\n✅ Транзакция подтверждена!");
+        println!("📝 Hash: {}", tx_hash);
+
+        Ok(tx_hash)
+    }
+
+    // Сообщение подтверждено, когда seqno кошелька вырос и среди транзакций кошелька есть наше
+    // внешнее сообщение. Тот же seqno мог занять другой процесс: тогда наше уже не будет принято
+    async fn wait_for_seqno(&self, seqno: u32, valid_until: u64, msg_hash: &[u8; 32]) -> Result<()> {
+        let address = self.wallet.address()?;
+        let since = valid_until.saturating_sub(self.ttl as u64 + CONFIRMATION_GRACE_SECS);
+
+        wait_for_confirmation(&format!("seqno {}", seqno), valid_until, || async {
+            if self.get_seqno().await? <= seqno {
+                return Ok(false);
+            }
+            Ok(self.find_transaction(&address, msg_hash, since).await?.is_some())
+        })
+        .await
+    }
+
+    // Hash транзакции address, в которой обработано внешнее сообщение msg_hash. Транзакции
+    // просматриваются от новых к старым, пока не станут старше since (время отправки с запасом)
+    async fn find_transaction(&self, address: &TonAddress, msg_hash: &[u8; 32], since: u64) -> Result<Option<String>> {
+        let is_ours = |hash: &str| {
+            general_purpose::STANDARD
+                .decode(hash)
+                .or_else(|_| general_purpose::URL_SAFE.decode(hash))
+                .is_ok_and(|hash| hash == msg_hash)
+        };
+
+        let mut cursor: Option<TransactionId> = None;
+        loop {
+            let mut query = vec![("address", address.to_raw()), ("limit", TRANSACTIONS_PAGE_SIZE.to_string())];
+            if let Some(id) = &cursor {
+                query.push(("lt", id.lt.clone()));
+                query.push(("hash", id.hash.clone()));
+            }
+
+            let response = self
+                .api_request(reqwest::Method::GET, "getTransactions")
+                .query(&query)
+                .send()
+                .await
+                .map_err(ChainError::from)?;
+
+            let status = response.status();
+            let body = response.text().await.map_err(ChainError::from)?;
+
+            let transactions = serde_json::from_str::<TransactionsResponse>(&body)
+                .ok()
+                .filter(|r| r.ok)
+                .and_then(|r| r.result)
+                .ok_or_else(|| ChainError::Rejected { status: status.as_u16(), body })?;
+            let full_page = transactions.len() >= TRANSACTIONS_PAGE_SIZE;
+
+            // Страница начинается с транзакции-курсора, она уже проверена
+            let mut oldest = None;
+            for tx in transactions.iter().filter(|tx| Some(&tx.transaction_id) != cursor.as_ref()) {
+                if tx.in_msg.as_ref().is_some_and(|msg| is_ours(&msg.hash)) {
+                    return Ok(Some(tx.transaction_id.hash.clone()));
+                }
+                oldest = Some(tx);
+            }
+
+            match oldest {
+                Some(tx) if full_page && tx.utime >= since => cursor = Some(tx.transaction_id.clone()),
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    // Ошибка сети или 5xx после отправки не значит, что сообщение не дошло до сети:
+    // такие ошибки не возвращаются, результат выясняется ожиданием подтверждения
+    async fn submit_boc(&self, boc: &str) -> Result<()> {
+        match self.send_boc(boc).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_retryable() => {
+                eprintln!("⚠️ sendBoc: {}. Проверяем, дошло ли сообщение...", e);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn api_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.with_api_key(self.client.request(method, format!("{}/{}", self.wallet.network.api_url(), path)))
+    }
+
+    fn index_request(&self, path: &str) -> reqwest::RequestBuilder {
+        self.with_api_key(self.client.get(format!("{}/{}", self.wallet.network.index_url(), path)))
+    }
+
+    fn with_api_key(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if TONCENTER_API_KEY.is_empty() {
+            request
+        } else {
+            request.header("X-API-Key", TONCENTER_API_KEY)
+        }
+    }
+
+    // Возвращает hash внешнего сообщения, по которому транзакцию можно найти в обозревателе
+    async fn send_boc(&self, boc: &str) -> Result<String> {
+        let request = SendBocRequest {
+            boc: boc.to_string(),
+        };
+
+        let response = self
+            .api_request(reqwest::Method::POST, "sendBoc")
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(ChainError::from)?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(ChainError::from)?;
+
+        if !status.is_success() {
+            return Err(ChainError::Rejected { status: status.as_u16(), body }.into());
+        }
+
+        Ok(hex::encode(TonCell::from_boc(boc)?.hash()))
+    }
+
+    // Вызов get-метода контракта; аргументы и результат в формате стека toncenter: ["num", "0x..."]
+    async fn run_get_method(&self, address: &str, method: &str, stack: Vec<Value>) -> Result<Vec<Value>> {
+        let response = self
+            .api_request(reqwest::Method::POST, "runGetMethod")
+            .json(&json!({
+                "address": address,
+                "method": method,
+                "stack": stack,
+            }))
+            .send()
+            .await
+            .map_err(ChainError::from)?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(ChainError::from)?;
+
+        let result = serde_json::from_str::<RunGetMethodResponse>(&body)
+            .ok()
+            .filter(|r| r.ok)
+            .and_then(|r| r.result)
+            .ok_or_else(|| ChainError::Rejected { status: status.as_u16(), body })?;
+
+        if result.exit_code != 0 {
+            return Err(ChainError::GetMethod { method: method.to_string(), exit_code: result.exit_code }.into());
+        }
+
+        Ok(result.stack)
+    }
+
+    // Публичный ключ кошелька (get_public_key есть у всех стандартных кошельков)
+    async fn get_public_key(&self, address: &TonAddress) -> Result<[u8; 32]> {
+        let stack = self.run_get_method(&address.to_raw(), "get_public_key", vec![]).await?;
+        let value = stack_num(stack.first())?;
+
+        let hex_key = format!("{:0>64}", value.trim_start_matches("0x"));
+        hex::decode(&hex_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| ChainError::InvalidStack(format!("некорректный публичный ключ: {}", value)).into())
+    }
+
+    // Адрес NFT коллекции по индексу (TEP-62)
+    async fn get_nft_address_by_index(&self, collection: &str, index: &[u8; 32]) -> Result<TonAddress> {
+        let stack = self
+            .run_get_method(
+                collection,
+                "get_nft_address_by_index",
+                vec![json!(["num", format!("0x{}", hex::encode(index))])],
+            )
+            .await?;
+
+        let cell = stack_cell(stack.first())?;
+        CellSlice::new(&cell)
+            .read_address()?
+            .ok_or_else(|| ChainError::InvalidStack("пустой адрес NFT".to_string()).into())
+    }
+
+    async fn get_nft_data(&self, address: &TonAddress) -> Result<NftItem> {
+        let stack = self.run_get_method(&address.to_raw(), "get_nft_data", vec![]).await?;
+
+        let initialized = stack_int(stack.get(0))? != 0;
+        let index = stack_num(stack.get(1))?;
+        let collection = stack_address(stack.get(2))?;
+        let owner = stack_address(stack.get(3))?;
+        let individual_content = stack_cell(stack.get(4))?;
+
+        // У элементов коллекции полное содержимое собирает сама коллекция
+        let content = match &collection {
+            Some(collection) => {
+                let stack = self
+                    .run_get_method(
+                        &collection.to_raw(),
+                        "get_nft_content",
+                        vec![json!(["num", index]), json!(["tvm.Cell", individual_content.to_boc()?])],
+                    )
+                    .await?;
+                NftContent::parse(&stack_cell(stack.first())?)
+            }
+            None => NftContent::parse(&individual_content),
+        };
+
+        Ok(NftItem {
+            address: *address,
+            initialized,
+            index,
+            collection,
+            owner,
+            content,
+        })
+    }
+
+    async fn get_collection_data(&self, address: &TonAddress) -> Result<NftCollection> {
+        let stack = self.run_get_method(&address.to_raw(), "get_collection_data", vec![]).await?;
+
+        Ok(NftCollection {
+            address: *address,
+            next_item_index: stack_int(stack.get(0))?,
+            content: NftContent::parse(&stack_cell(stack.get(1))?),
+            owner: stack_address(stack.get(2))?,
+        })
+    }
+
+    // Адреса NFT коллекции, которыми владеет адрес (по данным индексатора).
+    // Страницы загружаются, пока индексатор не вернет неполную
+    async fn find_owned_nfts(&self, owner: &TonAddress, collection: &TonAddress) -> Result<Vec<TonAddress>> {
+        let mut addresses = Vec::new();
+
+        loop {
+            let response = self
+                .index_request("nft/items")
+                .query(&[
+                    ("owner_address", owner.to_raw()),
+                    ("collection_address", collection.to_raw()),
+                    ("limit", NFT_INDEX_PAGE_SIZE.to_string()),
+                    ("offset", addresses.len().to_string()),
+                ])
+                .send()
+                .await
+                .map_err(ChainError::from)?;
+
+            let status = response.status();
+            let body = response.text().await.map_err(ChainError::from)?;
+
+            let result: NftIndexResponse = serde_json::from_str(&body)
+                .map_err(|_| ChainError::Rejected { status: status.as_u16(), body })?;
+
+            let page_len = result.nft_items.len();
+            for item in &result.nft_items {
+                addresses.push(TonAddress::parse(&item.address)?);
+            }
+            if page_len < NFT_INDEX_PAGE_SIZE {
+                return Ok(addresses);
+            }
+        }
+    }
+
+    // Передает NFT (username или номер) на другой кошелек. Излишек TON возвращается нам
+    async fn transfer_nft(
+        &self,
+        nft: &TonAddress,
+        new_owner: &TonAddress,
+        forward_amount: u64,
+        forward_payload: Option<&TonCell>,
+    ) -> Result<String> {
+        let owner = self.wallet.address()?;
+
+        // Без этой проверки контракт NFT просто отклонит перевод, а комиссия будет потеряна
+        let item = self.get_nft_data(nft).await?;
+        if item.owner != Some(owner) {
+            return Err(VerificationError::NotOwner {
+                nft: nft.to_raw(),
+                owner: item.owner.map(|a| a.to_raw()).unwrap_or_default(),
+            }.into());
+        }
+
+        println!("\n🎁 Передача NFT {}", item.name().unwrap_or_else(|| nft.to_friendly(true, self.wallet.network.is_testnet())));
+        println!("   Новый владелец: {}", new_owner.to_friendly(false, self.wallet.network.is_testnet()));
+
+        let query_id = chrono::Utc::now().timestamp_millis() as u64;
+        let body = nft_transfer_body(query_id, new_owner, Some(&owner), forward_amount, forward_payload);
+
+        self.send_message(&nft.to_raw(), NFT_TRANSFER_AMOUNT + forward_amount, &body).await
+    }
+
+    // Адрес jetton-кошелька владельца (TEP-74)
+    async fn get_jetton_wallet_address(&self, master: &TonAddress, owner: &TonAddress) -> Result<TonAddress> {
+        let mut owner_cell = TonCell::new();
+        owner_cell.write_ton_address(owner);
+
+        let stack = self
+            .run_get_method(
+                &master.to_raw(),
+                "get_wallet_address",
+                vec![json!(["tvm.Slice", owner_cell.to_boc()?])],
+            )
+            .await?;
+
+        stack_address(stack.first())?
+            .ok_or_else(|| ChainError::InvalidStack("пустой адрес jetton-кошелька".to_string()).into())
+    }
+
+    async fn get_jetton_wallet_data(&self, address: &TonAddress) -> Result<JettonWallet> {
+        let stack = self.run_get_method(&address.to_raw(), "get_wallet_data", vec![]).await?;
+
+        Ok(JettonWallet {
+            address: *address,
+            balance: stack_u128(stack.get(0))?,
+            owner: stack_address(stack.get(1))?,
+            master: stack_address(stack.get(2))?,
+        })
+    }
+
+    // Баланс jetton на нашем кошельке в минимальных единицах
+    async fn get_jetton_balance(&self, master: &TonAddress) -> Result<u128> {
+        let jetton_wallet = self.get_jetton_wallet_address(master, &self.wallet.address()?).await?;
+        self.jetton_wallet_balance(&jetton_wallet).await
+    }
+
+    async fn jetton_wallet_balance(&self, jetton_wallet: &TonAddress) -> Result<u128> {
+        match self.get_jetton_wallet_data(jetton_wallet).await {
+            Ok(data) => Ok(data.balance),
+            // Jetton-кошелек появляется только после первого поступления
+            Err(Error::Chain(ChainError::GetMethod { .. })) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Перевод jetton (например, USDT) с нашего кошелька. Комментарий получатель увидит
+    // в уведомлении, для этого вместе с ним пересылается 1 nanoTON
+    async fn transfer_jetton(
+        &self,
+        master: &TonAddress,
+        destination: &TonAddress,
+        amount: u128,
+        comment: Option<&str>,
+    ) -> Result<String> {
+        let owner = self.wallet.address()?;
+        let jetton_wallet = self.get_jetton_wallet_address(master, &owner).await?;
+
+        let balance = self.jetton_wallet_balance(&jetton_wallet).await?;
+        if balance < amount {
+            return Err(VerificationError::InsufficientBalance { balance, amount }.into());
+        }
+
+        let forward_payload = comment.map(comment_cell);
+        let forward_ton_amount = if forward_payload.is_some() { 1 } else { 0 };
+
+        println!("\n🪙 Перевод jetton");
+        println!("   Получатель: {}", destination.to_friendly(false, self.wallet.network.is_testnet()));
+        println!("   Сумма: {}", amount);
+
+        let query_id = chrono::Utc::now().timestamp_millis() as u64;
+        let body = jetton_transfer_body(
+            query_id,
+            amount,
+            destination,
+            Some(&owner),
+            forward_ton_amount,
+            forward_payload.as_ref(),
+        );
+
+        self.send_message(&jetton_wallet.to_raw(), JETTON_TRANSFER_AMOUNT + forward_ton_amount, &body).await
+    }
+
+    // Username и номера Fragment на нашем кошельке по коллекциям. Индексатор только подсказывает адреса,
+    // владелец и содержимое каждого NFT перепроверяются get-методами
+    async fn list_collectibles(&self) -> Result<Vec<OwnedCollection>> {
+        let owner = self.wallet.address()?;
+        let mut collections = Vec::new();
+
+        for kind in [CollectibleKind::Username, CollectibleKind::Number] {
+            let collection = TonAddress::parse(kind.collection_address())?;
+            let data = self.get_collection_data(&collection).await?;
+
+            let mut items = Vec::new();
+            for address in self.find_owned_nfts(&owner, &collection).await? {
+                let item = self.get_nft_data(&address).await?;
+                if item.owner == Some(owner) && item.collection == Some(collection) {
+                    items.push(item);
+                }
+            }
+            collections.push(OwnedCollection { collection: data, items });
+        }
+
+        Ok(collections)
+    }
+
+    // "active", "uninitialized" или "frozen"
+    async fn get_address_state(&self, address: &TonAddress) -> Result<String> {
+        let response = self
+            .api_request(reqwest::Method::GET, "getAddressState")
+            .query(&[("address", address.to_raw())])
+            .send()
+            .await
+            .map_err(ChainError::from)?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(ChainError::from)?;
+
+        serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|v| v.get("result").and_then(Value::as_str).map(|s| s.to_string()))
+            .ok_or_else(|| ChainError::Rejected { status: status.as_u16(), body }.into())
+    }
+
+    // toncenter знает seqno не всех версий кошелька; тогда он читается get-методом
+    async fn get_seqno(&self) -> Result<u32> {
+        let info = self.get_wallet_info().await?;
+        if let Some(seqno) = info.seqno {
+            return Ok(seqno);
+        }
+        if info.account_state != "active" {
+            return Ok(0);
+        }
+
+        let stack = self.run_get_method(&self.wallet.address()?.to_raw(), "seqno", vec![]).await?;
+        Ok(stack_int(stack.first())? as u32)
+    }
+
+    // Состояние кошелька в блокчейне: баланс и seqno (нет seqno — кошелек еще не задеплоен)
+    async fn get_wallet_info(&self) -> Result<WalletInfo> {
+        let response = self
+            .api_request(reqwest::Method::GET, "getWalletInformation")
+            .query(&[("address", self.wallet.address()?.to_raw())])
+            .send()
+            .await
+            .map_err(ChainError::from)?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(ChainError::from)?;
+
+        let result: WalletInfoResponse = serde_json::from_str(&body)
+            .map_err(|_| ChainError::Rejected { status: status.as_u16(), body: body.clone() })?;
+
+        result
+            .result
+            .filter(|_| result.ok)
+            .ok_or_else(|| ChainError::Rejected { status: status.as_u16(), body }.into())
+    }
+
+    async fn get_balance(&self) -> Result<String> {
+        println!("💰 Адрес кошелька: {}", self.wallet.get_address()?);
+
+        let info = self.get_wallet_info().await?;
+        println!("   Баланс: {:.4} TON", info.balance as f64 / 1e9);
+
+        Ok(info.balance.to_string())
+    }
+}
+
+// ОСНОВНОЙ ПРОЦЕСС
+// Клиент Fragment с сохраняемой сессией; при ее истечении вход выполняется кошельком покупателя
+fn open_fragment(
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<FragmentClient> {
+    let mnemonic_vec: Vec<String> = mnemonic.iter().map(|s| s.to_string()).collect();
+    let fragment = if CookieStore::secret_is_set(SESSION_SECRET) {
+        FragmentClient::persistent(fragment_hash, cookies_data, SESSION_PATH, SESSION_SECRET)?
+    } else {
+        eprintln!("⚠️ SESSION_SECRET не задан: сессия Fragment хранится только в памяти и не сохраняется на диск");
+        FragmentClient::new(fragment_hash, cookies_data)
+    };
+
+    // Сессия привязана к кошельку, который платит (см. TonTransaction::payer)
+    Ok(if USE_HIGHLOAD_WALLET {
+        fragment.with_session_wallet(HighloadWallet::new(&mnemonic_vec, 0)?.with_network(NETWORK))
+    } else {
+        fragment.with_session_wallet(TonWallet::new(&mnemonic_vec, 0)?.with_network(NETWORK).with_version(WALLET_VERSION))
+    })
+}
+
+// Чем проверяется транзакция от Fragment перед подписью
+enum PaymentCheck {
+    // Комментарий должен содержать описание покупки
+    Comment,
+    // Платеж должен уйти на контракт аукциона и не превышать ставку (в nanoTON)
+    Contract { address: TonAddress, max_amount: u64 },
+}
+
+// Оплата транзакции, полученной от Fragment (общий шаг для всех покупок).
+// Каждое сообщение счета проверяется отдельно, лимиты — по общей сумме
+async fn pay_link(
+    ton: &TonTransaction,
+    recipient: &str,
+    req_id: &str,
+    link: Vec<Message>,
+    marker: &str,
+    check: PaymentCheck,
+    options: &PurchaseOptions,
+) -> Result<PurchaseRecord> {
+    let mut amount_int: u64 = 0;
+    let mut comments = Vec::with_capacity(link.len());
+    for message in &link {
+        let amount: u64 = message
+            .amount
+            .parse()
+            .map_err(|_| VerificationError::InvalidAmount(message.amount.clone()))?;
+        amount_int = amount_int
+            .checked_add(amount)
+            .ok_or_else(|| VerificationError::InvalidAmount(message.amount.clone()))?;
+
+        let comment = match &check {
+            PaymentCheck::Comment => ton.verify_payload(&message.payload, marker)?,
+            PaymentCheck::Contract { address: expected, .. } => {
+                let actual = TonAddress::parse(&message.address)?;
+                if actual != *expected {
+                    return Err(VerificationError::UnexpectedDestination {
+                        expected: expected.to_raw(),
+                        actual: actual.to_raw(),
+                    }.into());
+                }
+                ton.decode_payload(&message.payload, marker)
+            }
+        };
+        comments.push(comment);
+    }
+    let amount_ton = amount_int as f64 / 1e9;
+
+    // Итоговая сумма может отличаться от котировки, поэтому лимит проверяется еще раз
+    if let Some(max_price) = options.max_price_ton {
+        if amount_ton > max_price {
+            return Err(VerificationError::PriceLimitExceeded { price: amount_ton, limit: max_price }.into());
+        }
+    }
+    // Fragment не должен списать больше запрошенной ставки, даже если лимит не задан
+    if let PaymentCheck::Contract { max_amount, .. } = check {
+        if amount_int > max_amount {
+            return Err(VerificationError::PriceLimitExceeded {
+                price: amount_ton,
+                limit: max_amount as f64 / 1e9,
+            }.into());
+        }
+    }
+
+    println!("✅ Сумма к оплате: {:.4} TON", amount_ton);
+    for message in &link {
+        println!("✅ Адрес Fragment: {}", message.address);
+    }
+    println!("ℹ️  Запрошено показать отправителя: {}", if options.show_sender { "да" } else { "нет" });
+
+    println!("\n💳 Шаг 4: Отправка транзакции в блокчейн...");
+    let tx_hash = ton.send_transaction(&link, marker).await?;
+
+    let record = PurchaseRecord {
+        product: marker.to_string(),
+        recipient: recipient.to_string(),
+        req_id: req_id.to_string(),
+        show_sender: options.show_sender,
+        address: link[0].address.clone(),
+        amount_nano: amount_int,
+        messages: link.len(),
+        comment: comments.join(" | "),
+        tx_hash,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+
+    // Транзакция уже отправлена: ошибка журнала не должна приводить к повторной оплате
+    if let Err(e) = save_purchase(&record) {
+        eprintln!("⚠️ Не удалось сохранить запись о покупке: {}", e);
+    }
+
+    Ok(record)
+}
+
+fn save_purchase(record: &PurchaseRecord) -> Result<()> {
+    use std::io::Write;
+
+    let line = serde_json::to_string(record)
+        .map_err(|e| ConfigError::Io(e.into()))?;
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(PURCHASES_PATH)
+        .map_err(ConfigError::from)?;
+    writeln!(file, "{}", line).map_err(ConfigError::from)?;
+
+    Ok(())
+}
+
+async fn buy_stars(
+    username: &str,
+    stars_count: i32,
+    options: &PurchaseOptions,
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<(bool, String)> {
+    let username = TelegramUsername::parse(username)?;
+
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
+    let ton = TonTransaction::new(mnemonic)?;
+
+    println!("{}", "=".repeat(60));
+    println!("🌟 ПОКУПКА TELEGRAM STARS");
+    println!("{}", "=".repeat(60));
+
+    // Проверка баланса
+    let _ = ton.get_balance().await;
+
+    // Шаг 1: Поиск получателя
+    println!("\n📍 Шаг 1: Поиск получателя {}...", username);
+    let found = fragment.fetch_recipient(&username).await?;
+    println!("✅ Получатель найден: {} ({})", found.name, found.recipient);
+    let recipient = found.recipient;
+
+    // Проверка цены до создания запроса
+    if let Some(max_price) = options.max_price_ton {
+        let quote = fragment.quote_stars(stars_count).await?;
+        println!("💱 Текущая цена: {:.4} TON (~${:.2})", quote.ton, quote.usd);
+
+        if quote.ton > max_price {
+            return Err(VerificationError::PriceLimitExceeded { price: quote.ton, limit: max_price }.into());
+        }
+    }
+
+    // Шаг 2: Создание запроса
+    println!("\n📝 Шаг 2: Создание запроса на {} звезд...", stars_count);
+    let req_id = fragment.fetch_req_id(&recipient, stars_count).await?;
+    println!("✅ Request ID: {}", req_id);
+
+    // Шаг 3: Получение данных транзакции
+    println!("\n🔍 Шаг 3: Получение данных транзакции...");
+    let link = fragment.fetch_buy_link(ton.payer(), &recipient, &req_id, stars_count, options.show_sender).await?;
+
+    // Шаг 4: Отправка TON
+    let record = pay_link(&ton, &recipient, &req_id, link, &format!("{} Telegram Stars", stars_count), PaymentCheck::Comment, options).await?;
+
+    println!("\n{}", "=".repeat(60));
+    println!("🎉 ПОКУПКА ЗАВЕРШЕНА УСПЕШНО!");
+    println!("{}", "=".repeat(60));
+
+    Ok((true, record.tx_hash))
 }
 
-impl TonTransaction {
-    fn new(mnemonic: &[&str]) -> Result<Self> {
-        let mnemonic_vec: Vec<String> = mnemonic.iter().map(|s| s.to_string()).collect();
-        let wallet = TonWallet::new(&mnemonic_vec, 0)?;
-        
-        Ok(Self {
-            wallet,
-            client: Client::builder()
-                .timeout(Duration::from_secs(30))
-                .build()
-                .unwrap(),
-        })
+async fn buy_premium(
+    username: &str,
+    months: u8,
+    options: &PurchaseOptions,
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<(bool, String)> {
+    if !PREMIUM_MONTHS.contains(&months) {
+        return Err(ConfigError::InvalidParameter("Premium можно подарить только на 3, 6 или 12 месяцев".to_string()).into());
     }
+    let username = TelegramUsername::parse(username)?;
 
-    fn decode_payload(&self, payload_base64: &str, stars_count: i32) -> String {
-        let fixed = fix_base64_padding(payload_base64);
-        
-        match general_purpose::STANDARD.decode(&fixed) {
-            Ok(decoded) => {
-                let decoded_text: String = decoded
-                    .iter()
-                    .map(|&b| if b >= 32 && b < 127 { b as char } else { ' ' })
-                    .collect();
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
+    let ton = TonTransaction::new(mnemonic)?;
 
-                let clean_text = Regex::new(r"\s+")
-                    .unwrap()
-                    .replace_all(&decoded_text, " ")
-                    .trim()
-                    .to_string();
+    println!("{}", "=".repeat(60));
+    println!("👑 ПОДАРОК TELEGRAM PREMIUM");
+    println!("{}", "=".repeat(60));
 
-                let pattern = format!(r"{} Telegram Stars.*", stars_count);
-                if let Ok(re) = Regex::new(&pattern) {
-                    if let Some(mat) = re.find(&clean_text) {
-                        return mat.as_str().to_string();
-                    }
-                }
+    // Проверка баланса
+    let _ = ton.get_balance().await;
 
-                clean_text
-            }
-            Err(_) => payload_base64.to_string(),
+    // Шаг 1: Поиск получателя
+    println!("\n📍 Шаг 1: Поиск получателя {}...", username);
+    let found = fragment.fetch_premium_recipient(&username, months).await?;
+    println!("✅ Получатель найден: {} ({})", found.name, found.recipient);
+    let recipient = found.recipient;
+
+    // Проверка цены до создания запроса
+    if let Some(max_price) = options.max_price_ton {
+        let quote = fragment.quote_premium(months).await?;
+        println!("💱 Текущая цена: {:.4} TON (~${:.2})", quote.ton, quote.usd);
+
+        if quote.ton > max_price {
+            return Err(VerificationError::PriceLimitExceeded { price: quote.ton, limit: max_price }.into());
         }
     }
 
-    async fn send_transaction(
-        &self,
-        recipient_address: &str,
-        amount_ton: f64,
-        payload_base64: &str,
-        stars_count: i32,
-    ) -> Result<String> {
-        println!("\n🔐 Инициализация кошелька...");
+    // Шаг 2: Создание запроса
+    println!("\n📝 Шаг 2: Создание запроса на {} мес. Premium...", months);
+    let req_id = fragment.fetch_premium_req_id(&recipient, months).await?;
+    println!("✅ Request ID: {}", req_id);
 
-        let wallet_address = self.wallet.get_address();
-        println!("✅ Адрес кошелька: {}", wallet_address);
+    // Шаг 3: Получение данных транзакции
+    println!("\n🔍 Шаг 3: Получение данных транзакции...");
+    let link = fragment.fetch_premium_link(ton.payer(), &recipient, &req_id, months, options.show_sender).await?;
 
-        let amount_nano = (amount_ton * 1e9) as u64;
-        let seqno = 0; // В реальности нужно получить с блокчейна
-        let payload_decoded = self.decode_payload(payload_base64, stars_count);
+    // Шаг 4: Отправка TON. В комментарии Fragment указан срок: "Telegram Premium for 3 months"
+    let marker = format!("Telegram Premium for {} months", months);
+    let record = pay_link(&ton, &recipient, &req_id, link, &marker, PaymentCheck::Comment, options).await?;
 
-        println!("\n💸 Отправка транзакции...");
-        println!("   Получатель: {}", recipient_address);
-        println!("   Сумма: {:.4} TON ({} nanoTON)", amount_ton, amount_nano);
-        println!("   Seqno: {}", seqno);
-        println!("   Комментарий: {}", payload_decoded);
+    println!("\n{}", "=".repeat(60));
+    println!("🎉 ПОКУПКА ЗАВЕРШЕНА УСПЕШНО!");
+    println!("{}", "=".repeat(60));
 
-        // Декодируем payload
-        let payload_cell = TonCell::from_boc(payload_base64)?;
+    Ok((true, record.tx_hash))
+}
 
-        // Создаем сообщение
-        let message = self.wallet.create_transfer_message(
-            recipient_address,
-            amount_nano,
-            &payload_cell,
-            seqno,
-        )?;
+async fn topup_ads(
+    account: &str,
+    amount_ton: u32,
+    options: &PurchaseOptions,
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<(bool, String)> {
+    if amount_ton == 0 {
+        return Err(ConfigError::InvalidParameter("Сумма пополнения должна быть больше нуля".to_string()).into());
+    }
+    let account = TelegramUsername::parse(account)?;
 
-        // Подписываем
-        let boc = self.wallet.sign_and_send(&message)?;
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
+    let ton = TonTransaction::new(mnemonic)?;
 
-        // Отправляем через API
-        let tx_hash = self.send_boc(&boc).await?;
+    println!("{}", "=".repeat(60));
+    println!("📢 ПОПОЛНЕНИЕ TELEGRAM ADS");
+    println!("{}", "=".repeat(60));
 
-        println!("\n✅ Транзакция отправлена успешно!");
-        println!("📝 Hash: {}", tx_hash);
+    // Проверка баланса
+    let _ = ton.get_balance().await;
 
-        Ok(tx_hash)
-    }
+    // Шаг 1: Поиск рекламного аккаунта
+    println!("\n📍 Шаг 1: Поиск рекламного аккаунта {}...", account);
+    let found = fragment.fetch_ads_recipient(&account).await?;
+    println!("✅ Аккаунт найден: {} ({})", found.name, found.recipient);
+    let recipient = found.recipient;
 
-    async fn send_boc(&self, boc: &str) -> Result<String> {
-        let request = SendBocRequest {
-            boc: boc.to_string(),
-        };
+    // Шаг 2: Создание запроса
+    println!("\n📝 Шаг 2: Создание запроса на {} TON...", amount_ton);
+    let req_id = fragment.fetch_ads_req_id(&recipient, amount_ton).await?;
+    println!("✅ Request ID: {}", req_id);
 
-        let response = self.client
-            .post(TON_API_ENDPOINT)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    // Шаг 3: Получение данных транзакции
+    println!("\n🔍 Шаг 3: Получение данных транзакции...");
+    let link = fragment.fetch_ads_link(ton.payer(), &recipient, &req_id, amount_ton).await?;
 
-        let body = response.text().await?;
-        
-        // Генерируем hash из BOC
-        let boc_bytes = general_purpose::STANDARD.decode(fix_base64_padding(boc))?;
-        let mut hasher = Sha256::new();
-        hasher.update(&boc_bytes);
-        let hash = hasher.finalize();
-        
-        Ok(hex::encode(hash))
-    }
+    // Шаг 4: Отправка TON
+    let record = pay_link(&ton, &recipient, &req_id, link, "Telegram Ads", PaymentCheck::Comment, options).await?;
 
-    async fn get_balance(&self) -> Result<String> {
-        println!("💰 Адрес кошелька: {}", self.wallet.get_address());
-        println!("   (Проверка баланса требует запроса к TON API)");
-        Ok("0".to_string())
-    }
+    println!("\n{}", "=".repeat(60));
+    println!("🎉 ПОПОЛНЕНИЕ ЗАВЕРШЕНО УСПЕШНО!");
+    println!("{}", "=".repeat(60));
+
+    Ok((true, record.tx_hash))
 }
 
-// ОСНОВНОЙ ПРОЦЕСС
-async fn buy_stars(
-    username: &str,
-    stars_count: i32,
+async fn buy_stars_giveaway(
+    channel: &str,
+    winners: u32,
+    stars_per_winner: u32,
+    options: &PurchaseOptions,
     fragment_hash: &str,
     cookies_data: &HashMap<&str, &str>,
     mnemonic: &[&str],
 ) -> Result<(bool, String)> {
-    let fragment = FragmentClient::new(fragment_hash, cookies_data);
+    if winners == 0 || stars_per_winner == 0 {
+        return Err(ConfigError::InvalidParameter("Количество победителей и звезд должно быть больше нуля".to_string()).into());
+    }
+
+    // Fragment принимает общее количество звезд на весь розыгрыш
+    let quantity = winners
+        .checked_mul(stars_per_winner)
+        .ok_or_else(|| ConfigError::InvalidParameter("Слишком большое количество звезд".to_string()))?;
+    let channel = TelegramUsername::parse(channel)?;
+
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
     let ton = TonTransaction::new(mnemonic)?;
 
     println!("{}", "=".repeat(60));
-    println!("🌟 ПОКУПКА TELEGRAM STARS");
+    println!("🎁 РОЗЫГРЫШ TELEGRAM STARS");
     println!("{}", "=".repeat(60));
 
     // Проверка баланса
     let _ = ton.get_balance().await;
 
-    // Шаг 1: Поиск получателя
-    println!("\n📍 Шаг 1: Поиск получателя {}...", username);
-    let recipient = fragment.fetch_recipient(username).await?;
-    println!("✅ Получатель найден: {}", recipient);
+    // Шаг 1: Поиск канала
+    println!("\n📍 Шаг 1: Поиск канала {}...", channel);
+    let found = fragment.fetch_giveaway_recipient(&channel).await?;
+    println!("✅ Канал найден: {} ({})", found.name, found.recipient);
+    let recipient = found.recipient;
 
     // Шаг 2: Создание запроса
-    println!("\n📝 Шаг 2: Создание запроса на {} звезд...", stars_count);
-    let req_id = fragment.fetch_req_id(&recipient, stars_count).await?;
+    println!(
+        "\n📝 Шаг 2: Создание розыгрыша: {} победителей по {} звезд...",
+        winners, stars_per_winner
+    );
+    let req_id = fragment.fetch_giveaway_req_id(&recipient, winners, quantity).await?;
     println!("✅ Request ID: {}", req_id);
 
     // Шаг 3: Получение данных транзакции
     println!("\n🔍 Шаг 3: Получение данных транзакции...");
-    let (address, amount, payload) = fragment.fetch_buy_link(&recipient, &req_id, stars_count).await?;
+    let link = fragment.fetch_giveaway_link(ton.payer(), &recipient, &req_id, winners, quantity).await?;
 
-    let amount_int: u64 = amount.parse()?;
-    let amount_ton = amount_int as f64 / 1e9;
+    // Шаг 4: Отправка TON. В комментарии — общее количество звезд розыгрыша
+    let marker = format!("{} Telegram Stars", quantity);
+    let record = pay_link(&ton, &recipient, &req_id, link, &marker, PaymentCheck::Comment, options).await?;
 
-    println!("✅ Сумма к оплате: {:.4} TON", amount_ton);
-    println!("✅ Адрес Fragment: {}", address);
+    println!("\n{}", "=".repeat(60));
+    println!("🎉 РОЗЫГРЫШ ОПЛАЧЕН УСПЕШНО!");
+    println!("{}", "=".repeat(60));
+
+    Ok((true, record.tx_hash))
+}
+
+// Ставка на аукционе username или номера
+async fn place_bid(
+    kind: CollectibleKind,
+    slug: &str,
+    bid_ton: f64,
+    options: &PurchaseOptions,
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<(bool, String)> {
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
+    let ton = TonTransaction::new(mnemonic)?;
+
+    println!("{}", "=".repeat(60));
+    println!("🔨 СТАВКА НА АУКЦИОНЕ");
+    println!("{}", "=".repeat(60));
+
+    // Шаг 1: Проверка лота
+    println!("\n📍 Шаг 1: Загрузка лота {}...", slug);
+    let item = fragment.fetch_auction_item(kind, slug).await?;
+    if item.status != AuctionStatus::OnAuction {
+        return Err(ConfigError::InvalidParameter(format!("{} не выставлен на аукцион ({:?})", item.slug, item.status)).into());
+    }
+    if let Some(min_bid) = item.min_bid_ton {
+        println!("✅ Минимальная ставка: {:.2} TON", min_bid);
+        if bid_ton < min_bid {
+            return Err(ConfigError::InvalidParameter(format!("Ставка {} TON меньше минимальной {} TON", bid_ton, min_bid)).into());
+        }
+    }
+    if let Some(max_price) = options.max_price_ton {
+        if bid_ton > max_price {
+            return Err(VerificationError::PriceLimitExceeded { price: bid_ton, limit: max_price }.into());
+        }
+    }
+
+    // Шаг 2: Адрес контракта аукциона в блокчейне
+    println!("\n📝 Шаг 2: Проверка контракта аукциона...");
+    let contract = ton.get_nft_address_by_index(kind.collection_address(), &kind.item_index(&item.slug)).await?;
+    println!("✅ Контракт: {}", contract.to_friendly(true, ton.wallet.network.is_testnet()));
+
+    // Шаг 3: Получение данных транзакции
+    println!("\n🔍 Шаг 3: Получение данных транзакции...");
+    let link = fragment.fetch_bid_link(ton.payer(), kind, &item.slug, bid_ton).await?;
 
     // Шаг 4: Отправка TON
-    println!("\n💳 Шаг 4: Отправка транзакции в блокчейн...");
-    let tx_hash = ton.send_transaction(&address, amount_ton, &payload, stars_count).await?;
+    let check = PaymentCheck::Contract { address: contract, max_amount: (bid_ton * 1e9).round() as u64 };
+    let record = pay_link(&ton, &item.slug, "", link, &item.slug, check, options).await?;
+
+    println!("\n{}", "=".repeat(60));
+    println!("🎉 СТАВКА ОТПРАВЛЕНА!");
+    println!("{}", "=".repeat(60));
+
+    Ok((true, record.tx_hash))
+}
+
+// Покупка username или номера, выставленного на продажу по фиксированной цене
+async fn buy_now(
+    kind: CollectibleKind,
+    slug: &str,
+    options: &PurchaseOptions,
+    fragment_hash: &str,
+    cookies_data: &HashMap<&str, &str>,
+    mnemonic: &[&str],
+) -> Result<(bool, String)> {
+    let fragment = open_fragment(fragment_hash, cookies_data, mnemonic)?;
+    let ton = TonTransaction::new(mnemonic)?;
+
+    println!("{}", "=".repeat(60));
+    println!("🛒 ПОКУПКА КОЛЛЕКЦИОННОГО ТОВАРА");
+    println!("{}", "=".repeat(60));
+
+    // Шаг 1: Проверка лота
+    println!("\n📍 Шаг 1: Загрузка лота {}...", slug);
+    let item = fragment.fetch_auction_item(kind, slug).await?;
+    let price = match (&item.status, item.price_ton) {
+        (AuctionStatus::ForSale, Some(price)) => price,
+        _ => return Err(ConfigError::InvalidParameter(format!("{} не продается по фиксированной цене ({:?})", item.slug, item.status)).into()),
+    };
+    println!("✅ Цена: {:.2} TON", price);
+    if let Some(max_price) = options.max_price_ton {
+        if price > max_price {
+            return Err(VerificationError::PriceLimitExceeded { price, limit: max_price }.into());
+        }
+    }
+
+    // Шаг 2: Адрес контракта продажи в блокчейне
+    println!("\n📝 Шаг 2: Проверка контракта...");
+    let contract = ton.get_nft_address_by_index(kind.collection_address(), &kind.item_index(&item.slug)).await?;
+    println!("✅ Контракт: {}", contract.to_friendly(true, ton.wallet.network.is_testnet()));
+
+    // Шаг 3: Получение данных транзакции
+    println!("\n🔍 Шаг 3: Получение данных транзакции...");
+    let link = fragment.fetch_bid_link(ton.payer(), kind, &item.slug, price).await?;
+
+    // Шаг 4: Отправка TON
+    let check = PaymentCheck::Contract { address: contract, max_amount: (price * 1e9).round() as u64 };
+    let record = pay_link(&ton, &item.slug, "", link, &item.slug, check, options).await?;
 
     println!("\n{}", "=".repeat(60));
     println!("🎉 ПОКУПКА ЗАВЕРШЕНА УСПЕШНО!");
     println!("{}", "=".repeat(60));
 
-    Ok((true, tx_hash))
+    Ok((true, record.tx_hash))
+}
+
+// Коллекционные username и номера на кошельке
+async fn show_inventory(mnemonic: &[&str]) -> Result<Vec<OwnedCollection>> {
+    let ton = TonTransaction::new(mnemonic)?;
+
+    println!("{}", "=".repeat(60));
+    println!("🗂 КОЛЛЕКЦИОННЫЕ ТОВАРЫ FRAGMENT");
+    println!("{}", "=".repeat(60));
+    println!("💰 Кошелек: {}", ton.wallet.get_address()?);
+
+    let collections = ton.list_collectibles().await?;
+    for owned in &collections {
+        let collection = &owned.collection;
+        match &collection.content {
+            NftContent::Offchain(url) => println!("\n📚 Коллекция {} ({})", collection.address.to_friendly(true, ton.wallet.network.is_testnet()), url),
+            _ => println!("\n📚 Коллекция {}", collection.address.to_friendly(true, ton.wallet.network.is_testnet())),
+        }
+        for item in &owned.items {
+            println!(
+                "   {} — {}",
+                item.name().unwrap_or_else(|| item.index.clone()),
+                item.address.to_friendly(true, ton.wallet.network.is_testnet())
+            );
+        }
+    }
+    println!("✅ Всего: {}", collections.iter().map(|owned| owned.items.len()).sum::<usize>());
+
+    Ok(collections)
 }
 
 #[tokio::main]
@@ -615,10 +4362,28 @@ async fn main() -> Result<()> {
     // Параметры покупки
     let username = "@example";  // Замените на реальный username
     let stars_count = 100;
+    let options = PurchaseOptions {
+        show_sender: false,  // true — получатель увидит, от кого подарок
+        max_price_ton: None,  // Например, Some(1.0) — не платить больше 1 TON
+    };
 
+    // Для подарка Premium: buy_premium(username, 3, &options, FRAGMENT_HASH, &DATA, &MNEMONIC)
+    // Для пополнения рекламы: topup_ads(username, 10, &options, FRAGMENT_HASH, &DATA, &MNEMONIC)
+    // Для розыгрыша в канале: buy_stars_giveaway("@channel", 10, 50, &options, FRAGMENT_HASH, &DATA, &MNEMONIC)
+    // Для ставки на аукционе: place_bid(CollectibleKind::Username, "@name", 100.0, &options, FRAGMENT_HASH, &DATA, &MNEMONIC)
+    // Вывод всего баланса: TonTransaction::new(&MNEMONIC)?.sweep("UQ...")
+    // Оплата с highload-кошелька: USE_HIGHLOAD_WALLET = true, его адрес — TonTransaction::new(&MNEMONIC)?.payer().get_address()
+    // Несколько оплат одной подписью: ton.send_batch(links.iter().map(OutgoingMessage::from_link).collect::<Result<_>>()?)
+    // Список username и номеров на кошельке: show_inventory(&MNEMONIC)
+    // Возврат клиенту: TonTransaction::new(&MNEMONIC)?.send_ton("UQ...", 500_000_000, Some("refund #42"))
+    // Номер заказа, который видит только клиент: TonTransaction::new(&MNEMONIC)?.send_ton_encrypted("UQ...", 10_000_000, "order 42")
+    // Выплата в USDT: TonTransaction::new(&MNEMONIC)?.transfer_jetton(&TonAddress::parse(USDT_MASTER)?, &customer, 1_000_000, Some("order 42"))
+    // Передача username клиенту: TonTransaction::new(&MNEMONIC)?.transfer_nft(&nft, &customer, 0, None)
+    // Для покупки по фиксированной цене: buy_now(CollectibleKind::Number, "+888 0123 4567", &options, FRAGMENT_HASH, &DATA, &MNEMONIC)
     match buy_stars(
         username,
         stars_count,
+        &options,
         FRAGMENT_HASH,
         &DATA,
         &MNEMONIC,
@@ -633,10 +4398,379 @@ async fn main() -> Result<()> {
             }
         }
         Err(e) => {
-            eprintln!("\n💥 Критическая ошибка: {}", e);
+            if e.is_retryable() {
+                eprintln!("\n⏳ Временная ошибка, можно повторить позже: {}", e);
+            } else {
+                eprintln!("\n💥 Критическая ошибка: {}", e);
+            }
             return Err(e);
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mnemonic() -> Vec<String> {
+        MNEMONIC.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn wallet_code_hashes() {
+        let v4r2 = TonCell::from_boc(WALLET_V4R2_CODE).unwrap();
+        assert_eq!(hex::encode(v4r2.hash()), "feb5ff6820e2ff0d9483e7e0d62c817d846789fb4ae580c878866d959dabd5c0");
+
+        let v5r1 = TonCell::from_boc(WALLET_V5R1_CODE).unwrap();
+        assert_eq!(hex::encode(v5r1.hash()), "20834b7b72b112147e1b2fb457b84e74d1a30f04f737d4f62a668e9552d2b72f");
+
+        let highload = TonCell::from_boc(HIGHLOAD_V3_CODE).unwrap();
+        assert_eq!(hex::encode(highload.hash()), "11acad7955844090f283bf238bc1449871f783e7cc0979408d3f4859483e8525");
+    }
+
+    #[test]
+    fn highload_query_ids() {
+        // После bit_number 1022 сразу следующий shift, 1023 не выдается
+        let id = HighloadQueryId((5 << 10) | 1022);
+        assert_eq!(id.next(), HighloadQueryId(6 << 10));
+        assert_eq!(HighloadQueryId(7).next(), HighloadQueryId(8));
+
+        // Резервный id не выдается: перед ним выдача начинается заново
+        let last = HighloadQueryId((8191 << 10) | 1021);
+        assert!(last.is_valid() && !HighloadQueryId::RESERVED.is_valid());
+        assert_eq!(last.next(), HighloadQueryId(0));
+
+        let allocator = QueryIdAllocator::new(10);
+        assert_eq!(allocator.allocate(100).unwrap(), HighloadQueryId(0));
+        assert_eq!(allocator.allocate(100).unwrap(), HighloadQueryId(1));
+        // Через 2 × timeout первый id снова свободен
+        assert_eq!(allocator.allocate(125).unwrap(), HighloadQueryId(2));
+        assert_eq!(allocator.state.lock().unwrap().issued.len(), 1);
+
+        // Некорректный next из старого файла не выдается
+        allocator.state.lock().unwrap().next = 1023;
+        assert_eq!(allocator.allocate(125).unwrap(), HighloadQueryId(0));
+        allocator.state.lock().unwrap().next = HighloadQueryId::RESERVED.0;
+        assert_eq!(allocator.allocate(125).unwrap(), HighloadQueryId(0));
+    }
+
+    #[test]
+    fn highload_query_ids_are_shared() {
+        let path = std::env::temp_dir().join(format!("fragment_query_ids_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_str().unwrap();
+
+        // Две покупки в одном процессе берут id из одного счетчика
+        let first = QueryIdAllocator::shared(path, 10).unwrap();
+        let second = QueryIdAllocator::shared(path, 10).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.allocate(100).unwrap(), HighloadQueryId(0));
+        assert_eq!(second.allocate(100).unwrap(), HighloadQueryId(1));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn boc_round_trip() {
+        for code in [WALLET_V4R2_CODE, WALLET_V5R1_CODE] {
+            let cell = TonCell::from_boc(code).unwrap();
+            let parsed = TonCell::from_boc(&cell.to_boc().unwrap()).unwrap();
+            assert_eq!(parsed, cell);
+            assert_eq!(parsed.hash(), cell.hash());
+        }
+
+        // Неполные байты, знаковые числа, coins и одна ячейка по двум ссылкам
+        let mut shared = TonCell::new();
+        shared.write_uint(0b101, 3);
+        let mut cell = TonCell::new();
+        cell.write_int(-1, 8);
+        cell.write_coins(1_000_000_000);
+        cell.write_bit(true);
+        cell.add_ref(shared.clone());
+        cell.add_ref(shared);
+
+        let boc = cell.to_boc_bytes().unwrap();
+        assert_eq!(TonCell::from_boc_bytes(&boc).unwrap(), cell);
+    }
+
+    #[test]
+    fn boc_rejects_bad_header() {
+        // Заголовок обещает 0xffffffff ячеек в BOC из 23 байт
+        let mut boc = vec![0xb5, 0xee, 0x9c, 0x72, 0x04, 0x01];
+        boc.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]); // cells
+        boc.extend_from_slice(&[0, 0, 0, 1]); // roots
+        boc.extend_from_slice(&[0, 0, 0, 0]); // absent
+        boc.push(0); // tot_cells_size
+        boc.extend_from_slice(&[0, 0, 0, 0]); // root
+        assert_eq!(boc.len(), 23);
+        assert!(TonCell::from_boc_bytes(&boc).is_err());
+
+        // Корень за пределами списка ячеек и недопустимый size_bytes
+        let valid = general_purpose::STANDARD.decode(TonCell::new().to_boc().unwrap()).unwrap();
+        assert!(TonCell::from_boc_bytes(&valid).is_ok());
+        let mut bad_root = valid.clone();
+        bad_root[10] = 1; // индекс корня при size_bytes = offset_bytes = 1
+        assert!(TonCell::from_boc_bytes(&bad_root).is_err());
+        let mut bad_size = valid;
+        bad_size[4] = (bad_size[4] & !0x07) | 0x07;
+        assert!(TonCell::from_boc_bytes(&bad_size).is_err());
+    }
+
+    #[test]
+    fn address_round_trip() {
+        let address = TonAddress::parse(TELEGRAM_USERNAMES_COLLECTION).unwrap();
+        assert_eq!(address.to_friendly(true, false), TELEGRAM_USERNAMES_COLLECTION);
+        assert_eq!(TonAddress::parse(&address.to_raw()).unwrap(), address);
+    }
+
+    #[test]
+    fn mnemonic_to_address() {
+        let (public_key, _) = TonCrypto::mnemonic_to_keys(&mnemonic()).unwrap();
+        assert_eq!(hex::encode(public_key), "2f787415a8324199bdb7cd2aca5e7684648f429ce55292c65ead258677875857");
+
+        // Адрес V5R1 этой мнемоники клиент раньше хранил константой FRAGMENT_ADDRESS
+        let wallet = TonWallet::new(&mnemonic(), 0).unwrap().with_version(WalletVersion::V5R1);
+        assert_eq!(
+            wallet.address().unwrap().to_raw(),
+            "0:20c429e3bb195f46a582c10eb687c6ed182ec58237a55787f245ec992c337118"
+        );
+    }
+
+    #[test]
+    fn send_mode_validation() {
+        let accepted = [
+            SendMode::DEFAULT,
+            SendMode::PAY_FEES_SEPARATELY,
+            SendMode::DESTROY_IF_ZERO,
+            SendMode::DEFAULT | SendMode::DESTROY_IF_ZERO,
+            SendMode::CARRY_REMAINING_BALANCE,
+            SendMode::CARRY_REMAINING_BALANCE | SendMode::DESTROY_IF_ZERO,
+            SendMode::CARRY_ALL_BALANCE,
+            SendMode::CARRY_ALL_BALANCE | SendMode::DESTROY_IF_ZERO,
+            SendMode::CARRY_ALL_BALANCE | SendMode::IGNORE_ERRORS | SendMode::BOUNCE_ON_ACTION_FAIL,
+        ];
+        for mode in accepted {
+            assert!(mode.validate().is_ok(), "mode {} должен проходить проверку", mode.bits());
+        }
+
+        let rejected = [
+            SendMode::CARRY_REMAINING_BALANCE | SendMode::CARRY_ALL_BALANCE,
+            SendMode::CARRY_REMAINING_BALANCE | SendMode::CARRY_ALL_BALANCE | SendMode::DESTROY_IF_ZERO,
+        ];
+        for mode in rejected {
+            assert!(mode.validate().is_err(), "mode {} должен отклоняться", mode.bits());
+        }
+
+        // Сообщение со всем балансом допустимо только последним в пачке
+        let wallet = TonWallet::new(&mnemonic(), 0).unwrap();
+        let dest = wallet.address().unwrap().to_raw();
+        let plain = OutgoingMessage::new(&dest, 1, TonCell::new()).unwrap();
+        let sweep = plain.clone().with_mode(SendMode::CARRY_ALL_BALANCE | SendMode::DESTROY_IF_ZERO);
+        assert!(wallet.create_transfer_message(&[plain.clone(), sweep.clone()], 0, 0).is_ok());
+        assert!(wallet.create_transfer_message(&[sweep, plain], 0, 0).is_err());
+    }
+
+    #[test]
+    fn comment_round_trip() {
+        let long = "Покупка 1000 Telegram Stars для @username. ".repeat(8);
+        for text in ["", "Telegram Premium for 3 months", long.as_str()] {
+            let cell = comment_cell(text);
+            assert_eq!(parse_comment(&cell).as_deref(), Some(text));
+
+            let parsed = TonCell::from_boc(&cell.to_boc().unwrap()).unwrap();
+            assert_eq!(parse_comment(&parsed).as_deref(), Some(text));
+        }
+        // Длинный комментарий не помещается в одну ячейку
+        assert!(long.len() > 123 + 127);
+        assert_eq!(comment_cell(&long).refs.len(), 1);
+
+        // Другой op — уже не текстовый комментарий
+        assert_eq!(parse_comment(&snake_cell(ENCRYPTED_COMMENT_OP, b"text")), None);
+    }
+
+    #[test]
+    fn encrypted_comment_round_trip() {
+        let alice = TonWallet::new(&mnemonic(), 0).unwrap();
+        let mut other = mnemonic();
+        other.reverse();
+        let bob = TonWallet::new(&other, 0).unwrap();
+        let bob_key: [u8; 32] = bob.public_key.as_slice().try_into().unwrap();
+
+        let text = "Заказ #42: 1000 Telegram Stars для @username";
+        let cell = alice.encrypt_comment(text, &bob_key).unwrap();
+        let cell = TonCell::from_boc(&cell.to_boc().unwrap()).unwrap();
+        assert_eq!(parse_comment(&cell), None);
+
+        // Солью служит адрес отправителя: расшифровать могут и получатель, и сам отправитель
+        let sender = alice.address().unwrap();
+        assert_eq!(bob.decrypt_comment(&cell, &sender).unwrap(), text);
+        assert_eq!(alice.decrypt_comment(&cell, &sender).unwrap(), text);
+
+        // С чужим адресом отправителя или текстовым комментарием расшифровка не проходит
+        assert!(bob.decrypt_comment(&cell, &bob.address().unwrap()).is_err());
+        assert!(bob.decrypt_comment(&comment_cell(text), &sender).is_err());
+    }
+
+    #[test]
+    fn payload_marker_is_whole_token() {
+        let ton = TonTransaction::new(&MNEMONIC).unwrap();
+        let payload = |text: &str| comment_cell(text).to_boc().unwrap();
+
+        let comment = ton.verify_payload(&payload("100 Telegram Stars \n\nRef#abc"), "100 Telegram Stars").unwrap();
+        assert!(comment.starts_with("100 Telegram Stars"));
+        assert!(ton.verify_payload(&payload("1100 Telegram Stars \n\nRef#abc"), "100 Telegram Stars").is_err());
+        assert!(ton.verify_payload(&payload("100 Telegram Stars"), "1100 Telegram Stars").is_err());
+
+        let premium = "Telegram Premium for 3 months";
+        assert!(ton.verify_payload(&payload("Telegram Premium for 3 months \n\nRef#abc"), premium).is_ok());
+        assert!(ton.verify_payload(&payload("Telegram Premium for 12 months \n\nRef#abc"), premium).is_err());
+    }
+
+    #[test]
+    fn api_hash_from_saved_pages() {
+        // По одной сохраненной странице на каждый путь из FRAGMENT_HASH_PAGES
+        let pages = [
+            ("/", include_str!("fixtures/home.html")),
+            ("/stars/buy", include_str!("fixtures/stars_buy.html")),
+        ];
+        assert_eq!(pages.map(|(path, _)| path), FRAGMENT_HASH_PAGES);
+
+        for (_, html) in pages {
+            assert_eq!(parse_api_hash(html).as_deref(), Some("4dcf6c0a1d0d2e5e14"));
+        }
+        assert_eq!(
+            parse_ton_proof_payload(pages[0].1).as_deref(),
+            Some("d3f0a31c7b2e4a96bf1870d2c4e6a5b1")
+        );
+    }
+
+    #[test]
+    fn invalid_hash_error() {
+        assert!(matches!(
+            FragmentError::from_message("Invalid hash", ""),
+            FragmentError::InvalidHash { .. }
+        ));
+        // Другие ошибки со словом hash не должны запускать обновление hash
+        assert!(matches!(
+            FragmentError::from_message("Transaction hash not found", ""),
+            FragmentError::NotFound { .. }
+        ));
+        assert!(matches!(
+            FragmentError::from_message("Invalid transaction hash", ""),
+            FragmentError::Api { .. }
+        ));
+    }
+
+    #[test]
+    fn auction_lists_from_saved_pages() {
+        let lots = parse_auction_list(include_str!("fixtures/auctions_list.html"));
+        assert_eq!(lots.len(), 2);
+        assert_eq!(lots[0].kind, CollectibleKind::Username);
+        assert_eq!(lots[0].slug, "crypto");
+        assert_eq!(lots[0].name, "@crypto");
+        assert_eq!(lots[0].status, AuctionStatus::OnAuction);
+        assert_eq!(lots[0].price_ton, Some(1050.0));
+        assert_eq!(lots[0].ends_at, Some(1792951200));
+        // Параметры ссылки не попадают в slug
+        assert_eq!(lots[1].slug, "wallet");
+        assert_eq!(lots[1].price_ton, Some(12500.5));
+        assert_eq!(lots[1].ends_at, Some(1792488600));
+
+        let lots = parse_auction_list(include_str!("fixtures/sale_list.html"));
+        assert_eq!(lots.len(), 1);
+        assert_eq!(lots[0].kind, CollectibleKind::Number);
+        assert_eq!(lots[0].slug, "88801234567");
+        assert_eq!(lots[0].name, "+888 0123 4567");
+        assert_eq!(lots[0].status, AuctionStatus::ForSale);
+        assert_eq!(lots[0].price_ton, Some(250.0));
+        assert_eq!(lots[0].ends_at, None);
+
+        let lots = parse_auction_list(include_str!("fixtures/sold_list.html"));
+        assert_eq!(lots.len(), 1);
+        assert_eq!(lots[0].name, "@news");
+        assert_eq!(lots[0].status, AuctionStatus::Sold);
+        assert_eq!(lots[0].price_ton, Some(88888.0));
+    }
+
+    #[test]
+    fn auction_items_from_saved_pages() {
+        let html = include_str!("fixtures/username_auction.html");
+        let item = parse_auction_item(html, CollectibleKind::Username, "crypto").unwrap();
+        assert_eq!(item.status, AuctionStatus::OnAuction);
+        assert_eq!(item.highest_bid_ton, Some(1050.0));
+        assert_eq!(item.min_bid_ton, Some(1103.0));
+        assert_eq!(item.price_ton, None);
+        assert_eq!(item.ends_at, Some(1792951200));
+        assert_eq!(item.owner.as_deref(), Some("EQCA14o1-VWhS2efqoh_9M1b_A9DtKTuoqfmkn83AbJzwnPi"));
+        assert_eq!(item.history.len(), 2);
+        assert_eq!(item.history[0].amount_ton, 1050.0);
+        assert_eq!(item.history[0].bidder.as_deref(), Some("UQAStv5GGBnXs6DdaCTy5RNRXILMp009_jdL1FcR3XOcvZe7"));
+        assert_eq!(item.history[0].placed_at, Some(1792326600));
+        assert_eq!(item.history[1].amount_ton, 1000.0);
+        assert_eq!(item.history[1].placed_at, Some(1792224000));
+
+        let html = include_str!("fixtures/number_sale.html");
+        let item = parse_auction_item(html, CollectibleKind::Number, "88801234567").unwrap();
+        assert_eq!(item.status, AuctionStatus::ForSale);
+        assert_eq!(item.price_ton, Some(250.0));
+        assert_eq!(item.highest_bid_ton, None);
+        assert_eq!(item.ends_at, None);
+        assert_eq!(item.owner.as_deref(), Some("UQAgxCnjuxlfRqWCwQ62h8btGC7FgjelV4fyReyZLDNxGFwA"));
+        assert!(item.history.is_empty());
+
+        let html = include_str!("fixtures/username_sold.html");
+        let item = parse_auction_item(html, CollectibleKind::Username, "news").unwrap();
+        assert_eq!(item.status, AuctionStatus::Sold);
+        assert_eq!(item.price_ton, None);
+        assert_eq!(item.history.len(), 1);
+        assert_eq!(item.history[0].amount_ton, 88888.0);
+        assert_eq!(item.history[0].placed_at, Some(1788274800));
+
+        // Страница входа вместо карточки товара
+        let html = include_str!("fixtures/login.html");
+        assert!(parse_auction_item(html, CollectibleKind::Username, "crypto").is_none());
+    }
+
+    #[tokio::test]
+    async fn confirmation_outcomes() {
+        let unconfirmed = |e: &Error| matches!(e, Error::Chain(ChainError::Unconfirmed { .. }));
+
+        // Срок истек, сообщение не подтверждено: его можно отправить заново
+        let e = wait_for_confirmation("seqno 1", 0, || async { Ok(false) }).await.unwrap_err();
+        assert!(matches!(e, Error::Chain(ChainError::Expired { .. })) && e.is_retryable());
+
+        // API недоступен до конца срока: результат неизвестен
+        let e = wait_for_confirmation("seqno 1", 0, || async {
+            Err(ChainError::Rejected { status: 503, body: String::new() }.into())
+        }).await.unwrap_err();
+        assert!(unconfirmed(&e) && !e.is_retryable());
+
+        let e = wait_for_confirmation("seqno 1", u64::MAX / 2, || async {
+            Err(ChainError::InvalidStack("seqno".to_string()).into())
+        }).await.unwrap_err();
+        assert!(unconfirmed(&e) && !e.is_retryable());
+
+        wait_for_confirmation("seqno 1", 0, || async { Ok(true) }).await.unwrap();
+    }
+
+    #[test]
+    fn cookie_store_round_trip() {
+        let path = std::env::temp_dir().join(format!("fragment_session_{}.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let cookies = HashMap::from([("stel_ssid".to_string(), "abc".to_string())]);
+
+        assert!(CookieStore::open(&path, SESSION_SECRET, cookies.clone()).is_err());
+
+        CookieStore::open(&path, "секрет сервиса", cookies).unwrap().save().unwrap();
+        let file = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&file).contains("abc"));
+
+        let store = CookieStore::open(&path, "секрет сервиса", HashMap::new()).unwrap();
+        assert!(store.is_valid("stel_ssid"));
+        assert!(CookieStore::open(&path, "другой секрет", HashMap::new()).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}